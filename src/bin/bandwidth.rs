@@ -0,0 +1,119 @@
+extern crate vel0city;
+extern crate nalgebra as na;
+
+use vel0city::assets;
+use vel0city::cli::arg;
+use vel0city::net::{
+    History,
+    MoverSnapshot,
+    PlayerSnapshot,
+    Precision,
+    ServerMessage,
+    Snapshot
+};
+use vel0city::player::movement::MoveInput;
+
+/// Strafes, turns and hops about, each bot a little differently.
+fn bot_input(bot: usize, tick: u64) -> MoveInput {
+    let t = tick as f32 * 0.005 + bot as f32;
+    let side = if (tick / 150 + bot as u64) % 2 == 0 { 1.0 } else { -1.0 };
+    MoveInput {
+        wishvel: na::Vec3::new(side * 200.0, 0.0, -320.0),
+        eyeang: na::Vec3::new((t * 0.7).sin() * 0.3, t * side, 0.0),
+        jump: (tick + bot as u64 * 37) % 120 < 20,
+        crouch: false,
+        hook: false,
+        reset: tick == 1,
+        fire: None,
+    }
+}
+
+/// Bytes sent so far with one way of encoding snapshots.
+struct Tally {
+    name: &'static str,
+    precision: Precision,
+    delta: bool,
+    history: History,
+    lasttick: u64,
+    bytes: usize,
+}
+impl Tally {
+    fn new(name: &'static str, precision: Precision, delta: bool) -> Tally {
+        Tally {
+            name: name,
+            precision: precision,
+            delta: delta,
+            history: History::new(),
+            lasttick: 0,
+            bytes: 0,
+        }
+    }
+
+    /// Encodes `game` as a client that got every earlier snapshot would be sent it.
+    fn send(&mut self, game: &vel0city::Game, players: &[PlayerSnapshot]) {
+        let tick = game.tickcount;
+        let basetick = if self.delta { self.lasttick } else { 0 };
+        let msg = ServerMessage::Snapshot(Snapshot {
+            tick: tick,
+            time: game.time,
+            basetick: basetick,
+            precision: self.precision,
+            ack: 0,
+            you: 0,
+            players: players.to_vec(),
+            projectiles: game.projectiles.clone(),
+            movers: MoverSnapshot::from_map(&game.map),
+        });
+        self.bytes += msg.encode(&self.history).len();
+        self.history.push(tick, players.iter().map(|pl| pl.quantized(self.precision)).collect());
+        self.lasttick = tick;
+    }
+}
+
+fn main() {
+    let mapname = arg("--map").unwrap_or("maps/test.bsp".to_string());
+    let nplayers: usize = arg("--players").and_then(|n| n.parse().ok()).unwrap_or(4);
+    let seconds: u64 = arg("--seconds").and_then(|n| n.parse().ok()).unwrap_or(30);
+    let snaprate: u64 = std::cmp::max(1, arg("--snaprate").and_then(|n| n.parse().ok()).unwrap_or(40));
+
+    let asset = assets::load_bin_asset(&mapname).unwrap();
+    let mut game = vel0city::Game::new(vel0city::map::q3_import::import(&asset).unwrap());
+    game.tickrate = arg("--tickrate").and_then(|t| t.parse().ok()).unwrap_or(200);
+    for _ in 0..nplayers {
+        game.add_player();
+    }
+
+    let mut tallies = vec![
+        Tally::new("full", Precision::Full, false),
+        Tally::new("full, delta", Precision::Full, true),
+        Tally::new("quantized", Precision::Quantized, false),
+        Tally::new("quantized, delta", Precision::Quantized, true),
+    ];
+
+    let ticks_per_snapshot = std::cmp::max(1, game.tickrate as u64 / snaprate);
+    let mut nsnapshots = 0;
+    for _ in 0..seconds * game.tickrate as u64 {
+        let tickcount = game.tickcount + 1;
+        let inputs: Vec<_> = (0..nplayers).map(|bot| bot_input(bot, tickcount)).collect();
+        game.tick(&inputs);
+        for pl in &mut game.players {
+            pl.events.clear();
+        }
+
+        if game.tickcount % ticks_per_snapshot == 0 {
+            let players: Vec<_> = game.players.iter().map(PlayerSnapshot::new).collect();
+            for tally in &mut tallies {
+                tally.send(&game, &players);
+            }
+            nsnapshots += 1;
+        }
+    }
+
+    let snapspersec = game.tickrate as f64 / ticks_per_snapshot as f64;
+    println!("{} players, {} snapshots at {:.1}/s", nplayers, nsnapshots, snapspersec);
+    for tally in &tallies {
+        let avg = tally.bytes as f64 / nsnapshots as f64;
+        println!("{:>18}: {:7.1} bytes/snapshot {:8.2} kbit/s",
+                 tally.name, avg, avg * snapspersec * 8.0 / 1000.0);
+    }
+}
@@ -10,6 +10,7 @@ use glium::DisplayBuild;
 use glium::Surface;
 
 use vel0city::assets;
+use vel0city::cli::arg;
 use vel0city::graphics::hud;
 use na::{
     Diag,
@@ -21,15 +22,44 @@ use std::f32::consts::{
     PI,
 };
 
-pub struct Client {
+/// One of the players sitting at this machine, with their own input, view and render targets.
+pub struct LocalPlayer {
+    /// Index of the player in `Game::players` this person controls.
+    player: usize,
     input: vel0city::input::Input,
+    viewfx: vel0city::viewfx::ViewEffects,
+    camera: vel0city::camera::Camera,
+    pass_data: vel0city::graphics::passes::PassData,
+    // The last two ticks' states, for blending between when rendering.
+    prevstate: vel0city::player::PlayerState,
+    curstate: vel0city::player::PlayerState,
+}
+impl LocalPlayer {
+    fn new(display: &glium::Display,
+           settings: vel0city::settings::InputSettings,
+           player: usize,
+           pl: &vel0city::player::Player,
+           size: (u32, u32)) -> LocalPlayer {
+        LocalPlayer {
+            player: player,
+            input: vel0city::input::Input::new(settings),
+            viewfx: vel0city::viewfx::ViewEffects::new(std::default::Default::default()),
+            camera: vel0city::camera::Camera::new(),
+            pass_data: vel0city::graphics::passes::PassData::new(display, size),
+            prevstate: pl.state(),
+            curstate: pl.state(),
+        }
+    }
+}
+
+pub struct Client {
+    locals: Vec<LocalPlayer>,
     hudmanager: vel0city::graphics::hud::HudManager,
     hudelements: Vec<hud::Element>,
     scene: Option<vel0city::graphics::Scene>,
 }
 impl Client {
     fn new(display: &glium::Display) -> Client {
-        let input = vel0city::input::Input::new();
         let hudmanager = hud::HudManager::new(display);
 
         let tex = assets::load_bin_asset("textures/arrow.png").unwrap();
@@ -49,7 +79,7 @@ impl Client {
         }
 
         Client {
-            input: input,
+            locals: vec![],
             hudmanager: hudmanager,
             hudelements: vec![hud::Element {
                 transform: na::Iso2::new(na::zero(), na::zero()),
@@ -63,6 +93,25 @@ impl Client {
     }
 }
 
+/// Splits the window into one strip per local player, top to bottom.
+fn viewport(winsize: (u32, u32), idx: usize, count: usize) -> glium::Rect {
+    let height = winsize.1 / count as u32;
+    glium::Rect {
+        left: 0,
+        bottom: (count - 1 - idx) as u32 * height,
+        width: winsize.0,
+        height: height,
+    }
+}
+
+/// What a player does while the person controlling them flies the camera around: nothing.
+fn held_input(pl: &vel0city::player::Player) -> vel0city::player::movement::MoveInput {
+    vel0city::player::movement::MoveInput {
+        eyeang: pl.eyeang,
+        ..vel0city::player::movement::MoveInput::idle()
+    }
+}
+
 #[cfg(not(test))]
 fn main() {
     let display = glutin::WindowBuilder::new()
@@ -72,36 +121,36 @@ fn main() {
         .build_glium()
         .unwrap();
     let mut client = Client::new(&display);
-    let (x, y) = display.get_framebuffer_dimensions();
-
-    let proj = na::Persp3::new(x as f32 / y as f32, 90.0, 1.5, 4096.0).to_mat();
 
     let asset = assets::load_bin_asset("maps/test.bsp").unwrap();
-    let mut game = vel0city::Game {
-        movesettings: std::default::Default::default(),
-        players: vec![vel0city::player::Player {
-            pos: na::Pnt3::new(0.0, 0.0, 0.),
-            eyeheight: 0.0,
-            eyeang: na::zero(), 
-            viewpunch: na::zero(), 
-            viewpunch_vel: na::zero(), 
-            halfextents: vel0city::player::PLAYER_HALFEXTENTS,
-            vel: na::zero(),
-            flags: vel0city::player::PlayerFlags::empty(),
-            landtime: 0.0,
-            holdjumptime: 0.0,
-        }],
-        map: vel0city::map::q3_import::import(&asset).unwrap(),
-        timescale: 1.0,
-        time: 0.0,
-    };
+    let mut game = vel0city::Game::new(vel0city::map::q3_import::import(&asset).unwrap());
+    // Overrides the physics mode's standing hull.
+    let hull = arg("--hull").and_then(|name| vel0city::player::Hull::named(&name));
+    if let Some(hull) = hull {
+        game.movesettings.hull = hull;
+    }
+    let tickrate: Option<u32> = arg("--tickrate").and_then(|t| t.parse().ok());
+    if let Some(tickrate) = tickrate {
+        // Some modes tick at a fixed framerate instead, like on the server.
+        if game.movesettings.maxfps > 0 {
+            println!("--tickrate can't be used with {} physics, which runs at {}fps",
+                     game.movesettings.mode, game.movesettings.maxfps);
+            return;
+        }
+        game.tickrate = tickrate;
+    }
+    game.determinism.enabled = std::env::args().any(|a| a == "--deterministic");
+    if std::env::args().any(|a| a == "--coop") {
+        game.mode = vel0city::settings::GameMode::Coop;
+    }
 
     let mapmodel = vel0city::map::q3_import::import_graphics_model(&asset, &display).unwrap();
     let ents = vel0city::map::q3_import::import_entities(&asset).unwrap();
     println!("{}", ents);
     client.scene = Some(vel0city::graphics::Scene {
         map: mapmodel,
-        lights: vec![ vel0city::graphics::Light { position: na::zero(), intensity: 0.0, radius: 0.5, color: na::Vec3::new(0.0, 1.0, 1.0) }] 
+        lights: vec![],
+        model_offsets: vec![na::zero(); game.map.models.len()],
     });
     
     let mut winsize;
@@ -112,6 +161,40 @@ fn main() {
     }
     //client.input.cursorpos = (winsize.0 as i32 / 2, winsize.1 as i32 / 2);
 
+    // Online, there's one player here, and the server says which one it is.
+    let mut connection = arg("--connect").map(|addr| {
+        let conn = vel0city::net::Connection::connect(&addr[..]).unwrap();
+        println!("Connecting to {}", addr);
+        conn
+    });
+    // How many people are playing split-screen. The first uses the mouse and keyboard and the second
+    // the other side of the keyboard. glutin can't read gamepads, so there's no room for a third.
+    let nlocals = if connection.is_some() {
+        1
+    } else {
+        let players = arg("--players").and_then(|n| n.parse().ok()).unwrap_or(1);
+        if players > 2 {
+            println!("Only 2 local players are supported, since gamepads can't be read; starting with 2");
+        }
+        std::cmp::min(std::cmp::max(players, 1), 2)
+    };
+    let mut prediction = vel0city::net::Prediction::new();
+    // Two snapshots' worth at the default snapshot rate, so one can go missing without a hitch.
+    let interpdelay = arg("--interp").and_then(|t| t.parse().ok()).unwrap_or(0.05);
+    let mut interpolation = vel0city::net::Interpolation::new(interpdelay, 0.05);
+    // The newest snapshot we've acted on.
+    let mut lastsnapshot = 0;
+    for idx in 0..nlocals {
+        let settings = if idx == 0 {
+            vel0city::settings::InputSettings::mouse_and_keyboard()
+        } else {
+            vel0city::settings::InputSettings::keyboard_only()
+        };
+        let plidx = game.add_player();
+        let rect = viewport(winsize, idx, nlocals);
+        client.locals.push(LocalPlayer::new(&display, settings, plidx, &game.players[plidx], (rect.width, rect.height)));
+    }
+
     let psystem = vel0city::graphics::passes::PassSystem::new(&display);
     let cel_program = glium::Program::from_source(
         &display,
@@ -142,9 +225,6 @@ fn main() {
         }
     };
 
-    let mut pass_data = vel0city::graphics::passes::PassData::new(&display, (winsize.0, winsize.1)); 
-    
-    let tick = 1.0/200.0;
     let mut lasttime = clock_ticks::precise_time_s();
     let mut accumtime = 0.0;
     let mut smoothtime = 0.0;
@@ -162,65 +242,229 @@ fn main() {
                 &glutin::Event::Resized(width, height) => {
                     winsize = (width, height);
                     if winsize.0 < 2 { winsize.0 = 2; }
-                    if winsize.1 < 2 { winsize.1 = 2; }
-                    pass_data = vel0city::graphics::passes::PassData::new(&display, (winsize.0, winsize.1)); 
-                    client.input.cursorpos = (winsize.0 as i32 / 2, winsize.1 as i32 / 2);
+                    if winsize.1 < 2 * nlocals as u32 { winsize.1 = 2 * nlocals as u32; }
+                    for (idx, local) in client.locals.iter_mut().enumerate() {
+                        let rect = viewport(winsize, idx, nlocals);
+                        local.pass_data = vel0city::graphics::passes::PassData::new(&display, (rect.width, rect.height)); 
+                        local.input.cursorpos = (winsize.0 as i32 / 2, winsize.1 as i32 / 2);
+                    }
+                },
+                &glutin::Event::KeyboardInput(glutin::ElementState::Pressed, _, Some(key)) 
+                    if key == client.locals[0].input.settings.physicskey && connection.is_none() => {
+                    let mode = game.movesettings.mode.next();
+                    game.set_physics(mode);
+                    if let Some(hull) = hull {
+                        game.movesettings.hull = hull;
+                    }
+                    if tickrate.is_some() && game.movesettings.maxfps > 0 {
+                        println!("{} physics runs at {}fps, ignoring --tickrate",
+                                 game.movesettings.mode, game.movesettings.maxfps);
+                    }
+                    // A run started under one mode can't finish under another.
+                    for pl in &mut game.players {
+                        pl.flags.insert(vel0city::player::PLAYER_MUST_DIE);
+                    }
+                    println!("Physics mode: {}", mode);
                 },
                 _ => ()
             }
 
-            client.input.handle_event(&win, &ev);
+            for local in &mut client.locals {
+                local.input.handle_event(&win, &ev);
+                if let &glutin::Event::KeyboardInput(glutin::ElementState::Pressed, _, Some(key)) = &ev {
+                    let nplayers = game.players.len();
+                    let followed = local.camera.target.unwrap_or(local.player);
+                    if Some(key) == local.input.settings.camerakey && followed < nplayers {
+                        let pl = &game.players[followed];
+                        local.camera.next_mode(pl.eyepos(), pl.eyeang);
+                        if local.camera.flying() {
+                            local.input.ang = pl.eyeang;
+                        }
+                        println!("Camera: {:?}", local.camera.mode);
+                    }
+                    if Some(key) == local.input.settings.followkey && nplayers > 0 {
+                        let next = (followed + 1) % nplayers;
+                        local.camera.target = if next == local.player { None } else { Some(next) };
+                    }
+                }
+            }
         }
 
 
-        if accumtime >= tick {
+        // This can change with the physics mode.
+        let tick = game.tick_interval();
+        if let Some(ref mut conn) = connection {
+            while accumtime >= tick {
+                accumtime -= tick;
+                let time = game.tick_length();
+                let local = &mut client.locals[0];
+                local.input.update(tick as f32);
+                let mi = if local.camera.flying() {
+                    held_input(&game.players[local.player])
+                } else {
+                    local.input.make_moveinput(&game.movesettings)
+                };
+                let sequence = conn.send_input(&mi);
+
+                local.prevstate = game.players[local.player].state();
+                prediction.predict(&mut game, local.player as u32, sequence, mi);
+                let pl = &mut game.players[local.player];
+                let events = std::mem::replace(&mut pl.events, vec![]);
+                for event in &events {
+                    local.viewfx.handle_event(event, pl, game.time);
+                }
+                local.viewfx.update(pl, time);
+                local.curstate = pl.state();
+            }
+            for msg in conn.poll() {
+                match msg {
+                    vel0city::net::ServerMessage::Welcome { physics, tickrate } => {
+                        println!("Connected, {} physics", physics);
+                        game.set_physics(physics);
+                        game.tickrate = tickrate;
+                    },
+                    vel0city::net::ServerMessage::Snapshot(snap) => {
+                        // Going back to an older state would undo inputs the server's already run.
+                        if snap.tick <= lastsnapshot {
+                            continue;
+                        }
+                        lastsnapshot = snap.tick;
+                        interpolation.push(snap.tick as f64 * game.tick_interval(), curtime, &snap.players);
+                        if let Some(you) = prediction.reconcile(&mut game, &snap) {
+                            let local = &mut client.locals[0];
+                            local.player = you;
+                            local.curstate = game.players[you].state();
+                        }
+                    },
+                }
+            }
+        } else if accumtime >= tick {
             while accumtime >= tick {
-                let mi = client.input.make_moveinput(&game.movesettings);
                 accumtime -= tick;
-                let timescale = game.timescale; // borrow checker hack
-                let time = tick as f32 * timescale;
-                game.time += time;
-                vel0city::player::movement::move_player(&mut game, 0, &mi, time);
-                // FIXME: hack 
-                client.input.ang = game.players[0].eyeang;
+                let time = game.tick_length();
+                let mut inputs = vec![];
+                for local in &mut client.locals {
+                    local.prevstate = game.players[local.player].state();
+                    local.input.update(tick as f32);
+                    inputs.push(if local.camera.flying() {
+                        held_input(&game.players[local.player])
+                    } else {
+                        local.input.make_moveinput(&game.movesettings)
+                    });
+                }
+                game.tick(&inputs);
+                for local in &mut client.locals {
+                    let pl = &mut game.players[local.player];
+                    let events = std::mem::replace(&mut pl.events, vec![]);
+                    for event in &events {
+                        local.viewfx.handle_event(event, pl, game.time);
+                    }
+                    local.viewfx.update(pl, time);
+                    // FIXME: hack 
+                    if !local.camera.flying() {
+                        local.input.ang = pl.eyeang;
+                    }
+                    local.curstate = pl.state();
+                }
+            }
+        }
+        // The free-fly camera moves every frame, so it's smooth whatever the tick rate.
+        for local in &mut client.locals {
+            if local.camera.flying() {
+                let mi = local.input.make_moveinput(&game.movesettings);
+                local.camera.fly(&mi, frametime as f32);
             }
         }
-        let pv = game.players[0].vel;
-
-        let ang = game.players[0].eyeang + game.players[0].viewpunch;
-        let rot = na::UnitQuat::new(na::Vec3::new(0.0, ang.y, 0.0));
-        let rot = rot.append_rotation(
-            &na::Vec3::new(PI + ang.x, 0.0, 0.0)
-            );
-
-        let l = na::Iso3::new_with_rotmat(na::zero(), rot.to_rot()).inv().unwrap().to_homogeneous();
-        let v = na::Iso3::new((game.players[0].pos.to_vec() + na::Vec3 { y: vel0city::player::PLAYER_HALFEXTENTS.y * -0.6, ..na::zero() }) * -1.0, na::zero()).to_homogeneous();
-        //l.inv();
-        let view = vel0city::graphics::View {
-            cam: l * v,
-            w2s: proj * l * v,
-        };
+
+        // How far we are between the last tick and the next one.
+        let alpha = (accumtime / tick) as f32;
+        let rendertime = game.time - game.tick_length() * (1.0 - alpha);
+        let renderstates: Vec<_> = client.locals.iter()
+            .map(|local| local.prevstate.lerp(&local.curstate, alpha))
+            .collect();
+        let remotetime = interpolation.render_time(curtime);
 
         let mut target = display.draw();
-        pass_data.get_framebuffer_for_prepass(&display).clear_depth(1.0);
+        // The server might not have told us who we are yet.
+        if client.locals.iter().any(|local| local.player >= game.players.len()) {
+            target.clear_color(0.0, 0.0, 0.0, 1.0);
+            target.finish();
+            continue;
+        }
+        let playerstates: Vec<_> = game.players.iter().enumerate().map(|(idx, pl)| {
+            match client.locals.iter().position(|local| local.player == idx) {
+                Some(localidx) => renderstates[localidx],
+                // Remote players are drawn from the snapshots we've had, a little in the past.
+                None => interpolation.sample(pl.id, remotetime).unwrap_or(pl.state())
+            }
+        }).collect();
         if let Some(ref mut scene) = client.scene {
-            scene.lights[0].position = game.players[0].pos.to_vec() + na::Vec3::new(0.0, vel0city::player::PLAYER_HALFEXTENTS.y * 0.1, 0.0);
-            scene.lights[0].intensity = na::clamp(na::norm(&na::Vec2::new(pv.x, pv.z)) / 5.0, 2.0, 50.0);
+            for entity in &game.map.entities {
+                scene.model_offsets[entity.model as usize] = entity.offset();
+            }
+            scene.lights.clear();
+            for (idx, pl) in game.players.iter().enumerate() {
+                let state = playerstates[idx];
+                scene.lights.push(vel0city::graphics::Light {
+                    position: state.pos.to_vec() + na::Vec3::new(0.0, pl.halfextents.y * 0.1, 0.0),
+                    intensity: na::clamp(na::norm(&na::Vec2::new(state.vel.x, state.vel.z)) / 5.0, 2.0, 50.0),
+                    radius: 0.5,
+                    color: na::Vec3::new(0.0, 1.0, 1.0)
+                });
+            }
+            for proj in &game.projectiles {
+                let color = match proj.kind {
+                    vel0city::weapon::WeaponKind::Rocket => na::Vec3::new(1.0, 0.5, 0.1),
+                    vel0city::weapon::WeaponKind::Plasma => na::Vec3::new(0.2, 0.4, 1.0),
+                };
+                scene.lights.push(vel0city::graphics::Light { position: proj.pos.to_vec(), intensity: 10.0, radius: 0.5, color: color });
+            }
+        }
 
+        for (idx, local) in client.locals.iter_mut().enumerate() {
+            let followed = match local.camera.target {
+                Some(target) if target < game.players.len() => target,
+                _ => local.player
+            };
+            let pl = &game.players[followed];
+            let state = playerstates[followed];
+            let rect = viewport(winsize, idx, nlocals);
+            let proj = na::Persp3::new(rect.width as f32 / rect.height as f32, 90.0, 1.5, 4096.0).to_mat();
 
-            vel0city::graphics::draw_scene(&mut pass_data.get_framebuffer_for_prepass(&display),
-                                           &scene,
-                                           &view);
-            psystem.light_passes(&display, &mut pass_data, &scene.lights, &view, &light_technique);
+            let mut eye = state.pos + pl.eye_offset();
+            if followed == local.player {
+                eye = eye + local.viewfx.offset(pl, rendertime);
+            }
+            let (eye, ang) = local.camera.view(&game.map, state.pos, eye, state.eyeang + pl.viewpunch);
+            let rot = na::UnitQuat::new(na::Vec3::new(0.0, ang.y, 0.0));
+            let rot = rot.append_rotation(
+                &na::Vec3::new(PI + ang.x, 0.0, 0.0)
+                );
 
-            psystem.postprocess(&pass_data, &mut target, &cel_technique);
-        };
-        let hudcontext = hud::Context {
-            eyeang: game.players[0].eyeang,
-            player_vel: game.players[0].vel
-        };
+            let l = na::Iso3::new_with_rotmat(na::zero(), rot.to_rot()).inv().unwrap().to_homogeneous();
+            let v = na::Iso3::new(eye.to_vec() * -1.0, na::zero()).to_homogeneous();
+            //l.inv();
+            let view = vel0city::graphics::View {
+                cam: l * v,
+                w2s: proj * l * v,
+            };
 
-        client.hudmanager.draw_elements(&mut target, &hudcontext, &client.hudelements);
+            local.pass_data.get_framebuffer_for_prepass(&display).clear_depth(1.0);
+            if let Some(ref scene) = client.scene {
+                vel0city::graphics::draw_scene(&mut local.pass_data.get_framebuffer_for_prepass(&display),
+                                               &scene,
+                                               &view);
+                psystem.light_passes(&display, &mut local.pass_data, &scene.lights, &view, &light_technique);
+
+                psystem.postprocess(&local.pass_data, &mut target, &cel_technique, Some(rect));
+            };
+            let hudcontext = hud::Context {
+                eyeang: pl.eyeang,
+                player_vel: pl.vel
+            };
+
+            client.hudmanager.draw_elements(&mut target, &hudcontext, &client.hudelements, Some(rect));
+        }
 
 
         target.finish();
@@ -0,0 +1,223 @@
+extern crate vel0city;
+extern crate clock_ticks;
+
+use std::collections::VecDeque;
+use std::net::{SocketAddr, UdpSocket};
+
+use vel0city::assets;
+use vel0city::cli::arg;
+use vel0city::net::{
+    self,
+    ClientMessage,
+    ServerMessage,
+    Snapshot,
+    PlayerSnapshot,
+    MoverSnapshot,
+    Precision,
+    History
+};
+use vel0city::player::movement::MoveInput;
+
+/// Clients we haven't heard from in this long are dropped.
+const CLIENT_TIMEOUT: f64 = 10.0;
+/// Inputs queued beyond this many ticks are thrown away, so a burst can't build up lag.
+const MAX_QUEUED_INPUTS: usize = 32;
+
+struct RemoteClient {
+    addr: SocketAddr,
+    /// Id of the player this client controls.
+    player: u32,
+    /// Inputs waiting to be run, oldest first.
+    inputs: VecDeque<(u32, MoveInput)>,
+    /// What to run when the queue runs dry: the last input, less any button presses.
+    lastinput: MoveInput,
+    /// Sequence number of the last input run.
+    ack: u32,
+    lastheard: f64,
+    /// Snapshots sent to this client, for delta-encoding against.
+    history: History,
+    /// The newest snapshot the client says it has.
+    snapack: u64,
+}
+
+fn main() {
+    let port = arg("--port").and_then(|p| p.parse().ok()).unwrap_or(net::DEFAULT_PORT);
+    let mapname = arg("--map").unwrap_or("maps/test.bsp".to_string());
+
+    let asset = assets::load_bin_asset(&mapname).unwrap();
+    let mut game = vel0city::Game::new(vel0city::map::q3_import::import(&asset).unwrap());
+    if let Some(name) = arg("--physics") {
+        match vel0city::settings::PhysicsMode::from_name(&name) {
+            Some(mode) => game.set_physics(mode),
+            None => {
+                println!("Unknown physics mode {}", name);
+                return;
+            }
+        }
+    }
+    if let Some(tickrate) = arg("--tickrate").and_then(|t| t.parse().ok()) {
+        // Those modes tick at a fixed framerate instead.
+        if game.movesettings.maxfps > 0 {
+            println!("--tickrate can't be used with {} physics, which runs at {}fps",
+                     game.movesettings.mode, game.movesettings.maxfps);
+            return;
+        }
+        game.tickrate = tickrate;
+    }
+    let snaprate: u64 = std::cmp::max(1, arg("--snaprate").and_then(|t| t.parse().ok()).unwrap_or(40));
+    let precision = match arg("--precision") {
+        Some(ref p) if p == "full" => Precision::Full,
+        _ => Precision::Quantized,
+    };
+    if std::env::args().any(|a| a == "--coop") {
+        game.mode = vel0city::settings::GameMode::Coop;
+    }
+
+    let socket = UdpSocket::bind(("0.0.0.0", port)).unwrap();
+    let incoming = net::spawn_receiver(&socket).unwrap();
+    println!("Serving {} on port {}", mapname, port);
+
+    // One client per player.
+    let mut clients: Vec<RemoteClient> = vec![];
+    let ticks_per_snapshot = std::cmp::max(1, (1.0 / (game.tick_interval() * snaprate as f64)).round() as u64);
+
+    let mut lasttime = clock_ticks::precise_time_s();
+    let mut accumtime = 0.0;
+    loop {
+        let curtime = clock_ticks::precise_time_s();
+        accumtime += curtime - lasttime;
+        lasttime = curtime;
+
+        while let Ok((data, from)) = incoming.try_recv() {
+            let msg = match ClientMessage::decode(&data) {
+                Ok(msg) => msg,
+                Err(e) => {
+                    println!("Bad packet from {}: {:?}", from, e);
+                    continue;
+                }
+            };
+            let idx = clients.iter().position(|c| c.addr == from);
+            match (msg, idx) {
+                (ClientMessage::Connect { version }, None) => {
+                    if version != net::PROTOCOL_VERSION {
+                        println!("{} has protocol {}, we have {}", from, version, net::PROTOCOL_VERSION);
+                        continue;
+                    }
+                    if clients.len() >= net::MAX_CLIENTS {
+                        println!("{} turned away, the server is full", from);
+                        continue;
+                    }
+                    println!("{} connected", from);
+                    let plidx = game.add_player();
+                    clients.push(RemoteClient {
+                        addr: from,
+                        player: game.players[plidx].id,
+                        inputs: VecDeque::new(),
+                        lastinput: MoveInput::idle(),
+                        ack: 0,
+                        lastheard: curtime,
+                        history: History::new(),
+                        snapack: 0,
+                    });
+                    let welcome = ServerMessage::Welcome {
+                        physics: game.movesettings.mode,
+                        tickrate: game.tickrate,
+                    };
+                    let _ = socket.send_to(&welcome.encode(&History::new()), &from);
+                },
+                (ClientMessage::Connect { .. }, Some(idx)) => {
+                    // Our welcome must have gone missing.
+                    clients[idx].lastheard = curtime;
+                    let welcome = ServerMessage::Welcome {
+                        physics: game.movesettings.mode,
+                        tickrate: game.tickrate,
+                    };
+                    let _ = socket.send_to(&welcome.encode(&History::new()), &from);
+                },
+                (ClientMessage::Input { sequence, snapack, input }, Some(idx)) => {
+                    let client = &mut clients[idx];
+                    client.lastheard = curtime;
+                    if snapack > client.snapack {
+                        client.snapack = snapack;
+                    }
+                    // Drop anything stale or reordered.
+                    let newest = client.inputs.back().map(|&(seq, _)| seq).unwrap_or(client.ack);
+                    if sequence > newest && client.inputs.len() < MAX_QUEUED_INPUTS {
+                        client.inputs.push_back((sequence, input));
+                    }
+                },
+                (ClientMessage::Disconnect, Some(idx)) => {
+                    println!("{} disconnected", from);
+                    let client = clients.remove(idx);
+                    if let Some(plidx) = game.player_index(client.player) {
+                        game.players.remove(plidx);
+                    }
+                },
+                _ => ()
+            }
+        }
+
+        let mut idx = 0;
+        while idx < clients.len() {
+            if curtime - clients[idx].lastheard > CLIENT_TIMEOUT {
+                println!("{} timed out", clients[idx].addr);
+                let client = clients.remove(idx);
+                if let Some(plidx) = game.player_index(client.player) {
+                    game.players.remove(plidx);
+                }
+            } else {
+                idx += 1;
+            }
+        }
+
+        let tick = game.tick_interval();
+        while accumtime >= tick {
+            accumtime -= tick;
+
+            let mut inputs = vec![];
+            for pl in &game.players {
+                let client = clients.iter_mut().find(|client| client.player == pl.id).unwrap();
+                inputs.push(match client.inputs.pop_front() {
+                    Some((sequence, input)) => {
+                        client.ack = sequence;
+                        client.lastinput = input.repeated();
+                        input
+                    },
+                    None => client.lastinput.clone()
+                });
+            }
+            game.tick(&inputs);
+            for pl in &mut game.players {
+                pl.events.clear();
+            }
+
+            if game.tickcount % ticks_per_snapshot == 0 {
+                let players: Vec<_> = game.players.iter().map(PlayerSnapshot::new).collect();
+                let quantized: Vec<_> = players.iter().map(|pl| pl.quantized(precision)).collect();
+                let movers = MoverSnapshot::from_map(&game.map);
+                for client in &mut clients {
+                    let basetick = if client.history.get(client.snapack).is_some() {
+                        client.snapack
+                    } else {
+                        0
+                    };
+                    let snap = ServerMessage::Snapshot(Snapshot {
+                        tick: game.tickcount,
+                        time: game.time,
+                        basetick: basetick,
+                        precision: precision,
+                        ack: client.ack,
+                        you: client.player,
+                        players: players.clone(),
+                        projectiles: game.projectiles.clone(),
+                        movers: movers.clone(),
+                    });
+                    let _ = socket.send_to(&snap.encode(&client.history), &client.addr);
+                    client.history.push(game.tickcount, quantized.clone());
+                }
+            }
+        }
+
+        std::thread::sleep_ms(1);
+    }
+}
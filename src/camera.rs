@@ -0,0 +1,162 @@
+use na;
+use map::Map;
+use map::bsp::MASK_SOLID;
+use map::cast::Ray;
+use player;
+use player::movement::MoveInput;
+
+/// How big a box the chase camera is, so the near plane never pokes through a wall.
+const CHASE_RADIUS: f32 = 4.0;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum CameraMode {
+    /// Through a player's eyes.
+    FirstPerson,
+    /// Behind a player, pulled in when walls are in the way.
+    Chase,
+    /// Flies anywhere, through walls. For mappers, and for watching.
+    FreeFly,
+}
+impl CameraMode {
+    pub fn next(self) -> CameraMode {
+        match self {
+            CameraMode::FirstPerson => CameraMode::Chase,
+            CameraMode::Chase => CameraMode::FreeFly,
+            CameraMode::FreeFly => CameraMode::FirstPerson,
+        }
+    }
+}
+
+pub struct Camera {
+    pub mode: CameraMode,
+    /// Which player to watch, or `None` for your own.
+    pub target: Option<usize>,
+    /// How far behind the player the chase camera sits.
+    pub chasedistance: f32,
+    /// Where the free-fly camera is and where it's looking.
+    pub pos: na::Pnt3<f32>,
+    pub ang: na::Vec3<f32>,
+    /// How fast the free-fly camera moves, in units per second.
+    pub flyspeed: f32,
+}
+impl Camera {
+    pub fn new() -> Camera {
+        Camera {
+            mode: CameraMode::FirstPerson,
+            target: None,
+            chasedistance: 64.0,
+            pos: na::Pnt3::new(0.0, 0.0, 0.0),
+            ang: na::zero(),
+            flyspeed: 400.0,
+        }
+    }
+
+    pub fn flying(&self) -> bool {
+        self.mode == CameraMode::FreeFly
+    }
+
+    /// Switches to the next mode. Free-fly starts wherever the view was.
+    pub fn next_mode(&mut self, eye: na::Pnt3<f32>, eyeang: na::Vec3<f32>) {
+        self.mode = self.mode.next();
+        if self.flying() {
+            self.pos = eye;
+            self.ang = eyeang;
+        }
+    }
+
+    /// Moves the free-fly camera the way `input` says; jump goes up and crouch goes down.
+    pub fn fly(&mut self, input: &MoveInput, dt: f32) {
+        self.ang = input.eyeang;
+        let yaw = na::Rot3::new(na::Vec3::new(0.0, self.ang.y, 0.0));
+        let pitch = na::Rot3::new(na::Vec3::new(self.ang.x, 0.0, 0.0));
+        let mut dir = na::rotate(&yaw, &na::rotate(&pitch, &input.wishvel));
+        if na::norm(&dir) > 0.0 {
+            dir = na::normalize(&dir);
+        }
+        if input.jump {
+            dir.y -= 1.0;
+        }
+        if input.crouch {
+            dir.y += 1.0;
+        }
+        if na::norm(&dir) > 0.0 {
+            self.pos = self.pos + na::normalize(&dir) * self.flyspeed * dt;
+        }
+    }
+
+    /// Where to draw from and which way to look, given the middle of the watched player's box and their eyes.
+    pub fn view(&self, map: &Map, center: na::Pnt3<f32>, eye: na::Pnt3<f32>,
+                eyeang: na::Vec3<f32>) -> (na::Pnt3<f32>, na::Vec3<f32>) {
+        match self.mode {
+            CameraMode::FirstPerson => (eye, eyeang),
+            CameraMode::Chase => (chase_pos(map, center, eye, eyeang, self.chasedistance), eyeang),
+            CameraMode::FreeFly => (self.pos, self.ang),
+        }
+    }
+}
+
+/// Backs away from `eye` along the view, stopping short of anything solid.
+/// Crouched or small hulls can have their eyes closer to the ceiling than the camera's box is big,
+/// so it starts out from `center` and goes as far up towards the eyes as it fits.
+fn chase_pos(map: &Map, center: na::Pnt3<f32>, eye: na::Pnt3<f32>, eyeang: na::Vec3<f32>,
+             distance: f32) -> na::Pnt3<f32> {
+    let start = cast_camera(map, center, eye - center);
+    cast_camera(map, start, player::view_forward(&eyeang) * -distance)
+}
+
+/// How far a camera-sized box gets from `orig` along `dir`.
+fn cast_camera(map: &Map, orig: na::Pnt3<f32>, dir: na::Vec3<f32>) -> na::Pnt3<f32> {
+    let ray = Ray {
+        orig: orig,
+        dir: dir,
+        halfextents: na::Vec3::new(CHASE_RADIUS, CHASE_RADIUS, CHASE_RADIUS),
+    };
+    let toi = match map.cast_ray(&ray, MASK_SOLID) {
+        Some(cast) => na::clamp(cast.toi, 0.0, 1.0),
+        None => 1.0
+    };
+    orig + dir * toi
+}
+
+#[cfg(test)]
+pub mod test {
+    use na;
+    use test::{floor_map, with_box};
+    use player::Hull;
+    use super::{Camera, CameraMode, CHASE_RADIUS};
+
+    #[test]
+    fn chase_stays_out_of_the_floor() {
+        let map = floor_map();
+        let mut camera = Camera::new();
+        camera.mode = CameraMode::Chase;
+        let eye = na::Pnt3::new(0.0, 0.0, 0.0);
+
+        // Looking level, there's nothing behind.
+        let (pos, _) = camera.view(&map, eye, eye, na::zero());
+        assert!(na::approx_eq_eps(&na::norm(&pos.to_vec()), &camera.chasedistance, &0.01));
+
+        // Looking up puts the camera below, but not through the floor.
+        let (pos, _) = camera.view(&map, eye, eye, na::Vec3::new(-1.2, 0.0, 0.0));
+        assert!(pos.y > 0.0);
+        assert!(pos.y <= 16.0 - 4.0 + 0.01);
+    }
+
+    #[test]
+    fn chase_stays_under_a_low_ceiling() {
+        // The floor's top is at 16 and the ceiling's underside at 2, right on a crouched player's head.
+        let map = with_box(na::Pnt3::new(0.0, -14.0, 0.0), na::Vec3::new(1024.0, 16.0, 1024.0));
+        let hull = Hull::standing().crouched();
+        let center = na::Pnt3::new(0.0, 16.0 - hull.halfextents.y, 0.0);
+        let eye = center + na::Vec3::new(0.0, -hull.eyeheight, 0.0);
+        assert!(eye.y - CHASE_RADIUS < 2.0);
+
+        let mut camera = Camera::new();
+        camera.mode = CameraMode::Chase;
+        let (pos, _) = camera.view(&map, center, eye, na::zero());
+        assert!(pos.y - CHASE_RADIUS >= 2.0);
+        assert!(pos.y + CHASE_RADIUS <= 16.0);
+        let across = na::Vec3::new(pos.x, 0.0, pos.z);
+        assert!(na::approx_eq_eps(&na::norm(&across), &camera.chasedistance, &0.01));
+    }
+}
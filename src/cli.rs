@@ -0,0 +1,9 @@
+use std;
+
+/// Reads `--<name> <value>` from the command line.
+pub fn arg(name: &str) -> Option<String> {
+    let args: Vec<String> = std::env::args().collect();
+    args.windows(2)
+        .find(|w| w[0] == name)
+        .map(|w| w[1].clone())
+}
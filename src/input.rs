@@ -2,6 +2,7 @@ use na;
 use glutin;
 use settings::InputSettings;
 use player::movement::MoveInput;
+use weapon::WeaponKind;
 use std::f32::consts::{
     PI_2,
     FRAC_PI_2
@@ -17,6 +18,14 @@ bitflags! {
         const BUTTON_RIGHT   = 0b00_00_10_00,
         const BUTTON_JUMP    = 0b00_01_00_00,
         const BUTTON_RESET   = 0b00_10_00_00,
+        const BUTTON_CROUCH  = 0b01_00_00_00,
+        const BUTTON_FIRE_ROCKET = 0b10_00_00_00,
+        const BUTTON_FIRE_PLASMA = 0b01_00_00_00_00,
+        const BUTTON_HOOK    = 0b10_00_00_00_00,
+        const BUTTON_LOOK_LEFT  = 0b01_00_00_00_00_00,
+        const BUTTON_LOOK_RIGHT = 0b10_00_00_00_00_00,
+        const BUTTON_LOOK_UP    = 0b01_00_00_00_00_00_00,
+        const BUTTON_LOOK_DOWN  = 0b10_00_00_00_00_00_00,
     }
 }
 
@@ -30,29 +39,13 @@ pub struct Input {
     pub settings: InputSettings
 }
 impl Input {
-    pub fn new() -> Input {
-        use glutin::VirtualKeyCode::*;
-
+    pub fn new(settings: InputSettings) -> Input {
         Input {
             ang: na::zero(),
             buttons: Buttons::empty(),
             cursorpos: (400, 300),
             hack: false,
-            settings: InputSettings {
-                sensitivity: 0.001,
-                /*
-                forwardkey: W,
-                backkey: A,
-                leftkey: S,
-                rightkey: D,
-                */
-                forwardkey: F,
-                backkey: N,
-                leftkey: Y,
-                rightkey: E,
-                resetkey: Escape,
-                jumpkey: Space
-            }
+            settings: settings,
         }
     }
     pub fn handle_event(&mut self,
@@ -60,6 +53,7 @@ impl Input {
                         event: &glutin::Event) {
         use glutin::Event::{
             MouseMoved,
+            MouseInput,
             KeyboardInput
         };
 
@@ -90,8 +84,47 @@ impl Input {
                 if vkcode == self.settings.resetkey { 
                     action(&mut self.buttons, BUTTON_RESET);
                 }
+                if vkcode == self.settings.crouchkey { 
+                    action(&mut self.buttons, BUTTON_CROUCH);
+                }
+                if vkcode == self.settings.hookkey { 
+                    action(&mut self.buttons, BUTTON_HOOK);
+                }
+                if Some(vkcode) == self.settings.rocketkey {
+                    action(&mut self.buttons, BUTTON_FIRE_ROCKET);
+                }
+                if Some(vkcode) == self.settings.plasmakey {
+                    action(&mut self.buttons, BUTTON_FIRE_PLASMA);
+                }
+                if Some(vkcode) == self.settings.lookleftkey {
+                    action(&mut self.buttons, BUTTON_LOOK_LEFT);
+                }
+                if Some(vkcode) == self.settings.lookrightkey {
+                    action(&mut self.buttons, BUTTON_LOOK_RIGHT);
+                }
+                if Some(vkcode) == self.settings.lookupkey {
+                    action(&mut self.buttons, BUTTON_LOOK_UP);
+                }
+                if Some(vkcode) == self.settings.lookdownkey {
+                    action(&mut self.buttons, BUTTON_LOOK_DOWN);
+                }
             },
-            &MouseMoved((absx, absy)) => {
+            &MouseInput(state, button) if self.settings.mouse => {
+                let action = |b: &mut Buttons, o| {
+                    match state {
+                        glutin::ElementState::Pressed => b.insert(o), 
+                        glutin::ElementState::Released => b.remove(o) 
+                    }
+                };
+
+                if button == self.settings.rocketbutton {
+                    action(&mut self.buttons, BUTTON_FIRE_ROCKET);
+                }
+                if button == self.settings.plasmabutton {
+                    action(&mut self.buttons, BUTTON_FIRE_PLASMA);
+                }
+            },
+            &MouseMoved((absx, absy)) if self.settings.mouse => {
                 if !self.hack { 
                     let (x, y) = (absx - self.cursorpos.0, absy - self.cursorpos.1);
                     let _ = window.set_cursor_position(self.cursorpos.0, self.cursorpos.1);
@@ -110,6 +143,24 @@ impl Input {
             _ => ()
         }
     }
+    /// Turns the view with the look keys.
+    pub fn update(&mut self, dt: f32) {
+        let turn = self.settings.turnspeed * dt;
+        if self.buttons.contains(BUTTON_LOOK_LEFT) {
+            self.ang.y -= turn;
+        }
+        if self.buttons.contains(BUTTON_LOOK_RIGHT) {
+            self.ang.y += turn;
+        }
+        if self.buttons.contains(BUTTON_LOOK_UP) {
+            self.ang.x -= turn;
+        }
+        if self.buttons.contains(BUTTON_LOOK_DOWN) {
+            self.ang.x += turn;
+        }
+        self.ang.y = (self.ang.y + PI_2) % PI_2;
+        self.ang.x = na::clamp(self.ang.x, -FRAC_PI_2, FRAC_PI_2);
+    }
     pub fn make_moveinput(&self, movesettings: &::settings::MoveSettings) -> MoveInput {
         let mut wvel: na::Vec3<f32> = na::zero();
         if self.buttons.contains(BUTTON_FORWARD) {
@@ -125,13 +176,25 @@ impl Input {
             wvel.x -= movesettings.movespeed;
         }
         let jump = self.buttons.contains(BUTTON_JUMP);
+        let crouch = self.buttons.contains(BUTTON_CROUCH);
+        let hook = self.buttons.contains(BUTTON_HOOK);
         let reset = self.buttons.contains(BUTTON_RESET);
+        let fire = if self.buttons.contains(BUTTON_FIRE_ROCKET) {
+            Some(WeaponKind::Rocket)
+        } else if self.buttons.contains(BUTTON_FIRE_PLASMA) {
+            Some(WeaponKind::Plasma)
+        } else {
+            None
+        };
 
         MoveInput {
             wishvel: wvel,
             eyeang: self.ang,
             jump: jump,
+            crouch: crouch,
+            hook: hook,
             reset: reset,
+            fire: fire,
         }
     }
     pub fn get_ang(&self) -> na::Vec3<f32> { 
@@ -20,22 +20,730 @@ pub use vel0city_base::assets as assets;
 pub use vel0city_map as map;
 pub use vel0city_graphics as graphics;
 
+pub mod camera;
+pub mod cli;
 pub mod input;
+pub mod net;
 pub mod player;
 pub mod particle;
 pub mod settings;
+pub mod viewfx;
+pub mod weapon;
 
 pub struct Game {
     pub map: map::Map,
     pub players: Vec<player::Player>,
+    pub projectiles: Vec<weapon::Projectile>,
 
+    pub mode: settings::GameMode,
     pub movesettings: settings::MoveSettings,
+    pub weaponsettings: settings::WeaponSettings,
+    pub healthsettings: settings::HealthSettings,
+    pub determinism: settings::Determinism,
     pub timescale: f32,
     pub time: f32,
+    /// Physics ticks per second. Unused when `movesettings.maxfps` is set.
+    pub tickrate: u32,
+    /// Ticks run since the game started.
+    pub tickcount: u64,
+    /// The id the next player added gets.
+    pub nextplayerid: u32,
+}
+
+impl Game {
+    pub fn new(map: map::Map) -> Game {
+        let mut game = Game {
+            map: map,
+            players: vec![],
+            projectiles: vec![],
+            mode: settings::GameMode::Race,
+            movesettings: std::default::Default::default(),
+            weaponsettings: std::default::Default::default(),
+            healthsettings: std::default::Default::default(),
+            determinism: std::default::Default::default(),
+            timescale: 1.0,
+            time: 0.0,
+            tickrate: 200,
+            tickcount: 0,
+            nextplayerid: 0,
+        };
+        game.set_physics(settings::PhysicsMode::Vel0city);
+        game
+    }
+
+    /// Adds a player with an id no one else in this game has had, returning their index.
+    pub fn add_player(&mut self) -> usize {
+        let mut pl = player::Player::new(&self.movesettings.hull);
+        pl.id = self.nextplayerid;
+        self.nextplayerid += 1;
+        self.players.push(pl);
+        self.players.len() - 1
+    }
+
+    /// Where the player with the given id is in `players`, if they're still here.
+    pub fn player_index(&self, id: u32) -> Option<usize> {
+        self.players.iter().position(|pl| pl.id == id)
+    }
+
+    /// Switches to a physics preset, keeping the map's own hull if it has one.
+    pub fn set_physics(&mut self, mode: settings::PhysicsMode) {
+        self.movesettings = settings::MoveSettings::preset(mode);
+        if let Some(hull) = self.map.hull.as_ref().and_then(|name| player::Hull::named(name)) {
+            self.movesettings.hull = hull;
+        }
+    }
+
+    /// How much real time passes between ticks.
+    pub fn tick_interval(&self) -> f64 {
+        if self.movesettings.maxfps > 0 {
+            // Q3 measures frames in whole milliseconds, so 125fps is 8ms but 333fps is 3ms.
+            (1000 / self.movesettings.maxfps) as f64 / 1000.0
+        } else {
+            1.0 / self.tickrate as f64
+        }
+    }
+
+    /// How much game time one tick covers.
+    pub fn tick_length(&self) -> f32 {
+        if self.determinism.enabled {
+            self.tick_interval() as f32
+        } else {
+            self.tick_interval() as f32 * self.timescale
+        }
+    }
+
+    /// Runs one tick, with one input per player. Players without an input sit the tick out,
+    /// and inputs without a player are ignored.
+    /// Everything happens in a fixed order: movers, then each player in turn, then projectiles.
+    pub fn tick(&mut self, inputs: &[player::movement::MoveInput]) {
+        let dt = self.begin_tick();
+        let nplayers = self.players.len();
+        for (idx, input) in inputs.iter().take(nplayers).enumerate() {
+            self.tick_player(idx as u32, input, dt);
+        }
+        self.end_tick(dt);
+    }
+
+    /// The first part of `tick`: moves the clock on and runs the movers.
+    /// Returns how much game time the tick covers.
+    pub fn begin_tick(&mut self) -> f32 {
+        let dt = self.tick_length();
+        self.tickcount += 1;
+        if self.determinism.enabled {
+            // Summing dt every tick drifts; this doesn't.
+            self.time = (self.tickcount as f64 * self.tick_interval()) as f32;
+        } else {
+            self.time += dt;
+        }
+        player::movement::update_movers(self, dt);
+        dt
+    }
+
+    /// Runs one player's input for the tick. Prediction runs its own player through this too,
+    /// so it has to stay exactly what the server does.
+    pub fn tick_player(&mut self, playeridx: u32, input: &player::movement::MoveInput, dt: f32) {
+        if self.determinism.enabled {
+            let input = input.quantized();
+            player::movement::move_player(self, playeridx, &input, dt);
+            weapon::fire_weapons(self, playeridx, &input);
+        } else {
+            player::movement::move_player(self, playeridx, input, dt);
+            weapon::fire_weapons(self, playeridx, input);
+        }
+    }
+
+    /// The last part of `tick`: runs the projectiles, then snaps everyone to the grid.
+    pub fn end_tick(&mut self, dt: f32) {
+        weapon::update_projectiles(self, dt);
+
+        if self.determinism.enabled && self.determinism.snap {
+            for pl in &mut self.players {
+                pl.pos = snap(&pl.pos.to_vec()).to_pnt();
+                pl.vel = snap(&pl.vel);
+            }
+        }
+    }
+}
+
+/// Rounds to a power-of-two grid, so tiny rounding differences don't compound.
+fn snap(v: &na::Vec3<f32>) -> na::Vec3<f32> {
+    let grid = 1.0 / 128.0;
+    na::Vec3::new((v.x / grid).round() * grid,
+                  (v.y / grid).round() * grid,
+                  (v.z / grid).round() * grid)
 }
 
 #[cfg(test)]
 pub mod test {
-    use super::{map, Game, player};
+    use super::{map, settings, weapon, Game, player};
+    use player::movement::MoveInput;
+    use na;
+
+    /// A big flat floor whose top is at y = 16.
+    pub fn floor_map() -> map::Map {
+        let side = |x: f32, y: f32, z: f32, dist: f32| map::bsp::BrushSide {
+            plane: map::bsp::Plane { norm: na::Vec3::new(x, y, z), dist: dist },
+            flags: 0,
+            contents: map::bsp::CONTENTS_SOLID,
+        };
+        map::Map {
+            bsp: map::bsp::Tree {
+                inodes: vec![map::bsp::InnerNode {
+                    plane: map::bsp::Plane { norm: na::Vec3::new(0.0, -1.0, 0.0), dist: -16.0 },
+                    pos: -1,
+                    neg: -1,
+                }],
+                leaves: vec![map::bsp::Leaf { leafbrush: 0, n_leafbrushes: 1 }],
+                brushes: vec![map::bsp::Brush {
+                    sides: vec![
+                        side(1.0, 0.0, 0.0, 1024.0),
+                        side(-1.0, 0.0, 0.0, 1024.0),
+                        side(0.0, 0.0, 1.0, 1024.0),
+                        side(0.0, 0.0, -1.0, 1024.0),
+                        side(0.0, 1.0, 0.0, 32.0),
+                        side(0.0, -1.0, 0.0, -16.0),
+                    ]
+                }],
+                leafbrushes: vec![0],
+            },
+            models: vec![map::Model {
+                mins: na::Vec3::new(-1024.0, 16.0, -1024.0),
+                maxs: na::Vec3::new(1024.0, 32.0, 1024.0),
+                face: 0,
+                n_faces: 0,
+                brush: 0,
+                n_brushes: 1,
+            }],
+            entities: vec![],
+            hull: None,
+        }
+    }
+
+    pub fn input(wishvel: na::Vec3<f32>, yaw: f32, jump: bool) -> MoveInput {
+        MoveInput {
+            wishvel: wishvel,
+            eyeang: na::Vec3::new(0.0, yaw, 0.0),
+            jump: jump,
+            crouch: false,
+            hook: false,
+            reset: false,
+            fire: None,
+        }
+    }
+
+    /// Strafes and hops about in a circle.
+    fn run_trajectory(timescale: f32) -> Vec<na::Pnt3<f32>> {
+        let mut game = Game::new(floor_map());
+        game.determinism.enabled = true;
+        game.determinism.snap = true;
+        game.timescale = timescale;
+        game.add_player();
+
+        let mut trajectory = vec![];
+        for i in 0..600 {
+            let yaw = i as f32 * 0.013;
+            let jump = i % 90 < 10;
+            game.tick(&[input(na::Vec3::new(150.0, 0.0, -220.0), yaw, jump)]);
+            trajectory.push(game.players[0].pos);
+        }
+        trajectory
+    }
+
+    #[test]
+    fn deterministic_runs_ignore_timescale() {
+        assert_eq!(run_trajectory(1.0), run_trajectory(0.25));
+    }
+
+    #[test]
+    fn sin_cos_is_the_same_everywhere() {
+        // Bit-exact, so a build or platform that does the maths differently fails here.
+        assert_eq!(player::sin_cos(0.3), (0.29552022, 0.9553365));
+        assert_eq!(player::sin_cos(1.0), (0.84147096, 0.54030234));
+        assert_eq!(player::sin_cos(2.5), (0.5984722, -0.8011436));
+        assert_eq!(player::sin_cos(-4.0), (0.7568025, -0.6536435));
+        let mut a = -10.0f32;
+        while a < 10.0 {
+            let (s, c) = player::sin_cos(a);
+            assert!(na::approx_eq_eps(&s, &a.sin(), &1e-5));
+            assert!(na::approx_eq_eps(&c, &a.cos(), &1e-5));
+            a += 0.01;
+        }
+    }
+
+    /// Speed gained across the way a player is flying from one tick of only strafing.
+    fn strafe_gain(mode: settings::PhysicsMode) -> f32 {
+        let mut game = Game::new(floor_map());
+        game.set_physics(mode);
+        game.add_player();
+        game.players[0].pos.y = -500.0;
+        game.players[0].vel = na::Vec3::new(0.0, 0.0, -300.0);
+        game.tick(&[input(na::Vec3::new(game.movesettings.movespeed, 0.0, 0.0), 0.0, false)]);
+        game.players[0].vel.x
+    }
+
+    #[test]
+    fn strafe_accel_follows_the_preset() {
+        // vel0city and VQ3 scale by the speed asked for, CPM by its strafe speed cap.
+        for &mode in &[settings::PhysicsMode::Vel0city, settings::PhysicsMode::Vq3, settings::PhysicsMode::Cpm] {
+            let mut game = Game::new(floor_map());
+            game.set_physics(mode);
+            let ms = &game.movesettings;
+            let scale = if ms.cappedstrafeaccel { ms.airstrafespeed } else { ms.movespeed };
+            let expected = na::clamp(ms.airstrafeaccel * scale * game.tick_length(), 0.0, ms.airstrafespeed);
+            assert!(na::approx_eq_eps(&strafe_gain(mode), &expected, &0.5));
+        }
+        // The same as it always was.
+        let game = Game::new(floor_map());
+        assert!(na::approx_eq_eps(&strafe_gain(settings::PhysicsMode::Vel0city), &(3.2 * 220.0 * game.tick_length()), &0.01));
+    }
+
+    #[test]
+    fn extra_inputs_are_ignored() {
+        let mut game = Game::new(floor_map());
+        game.add_player();
+        let idle = input(na::zero(), 0.0, false);
+        game.tick(&[idle.clone(), idle]);
+        assert_eq!(game.players.len(), 1);
+    }
+
+    /// Drops one player onto another, returning where the top one ends up.
+    fn drop_onto_player(mode: settings::GameMode) -> f32 {
+        let mut game = Game::new(floor_map());
+        game.mode = mode;
+        game.add_player();
+        game.add_player();
+        game.players[1].pos.y = -40.0;
+        for _ in 0..400 {
+            let idle = input(na::zero(), 0.0, false);
+            game.tick(&[idle.clone(), idle]);
+        }
+        game.players[1].pos.y
+    }
+
+    #[test]
+    fn players_only_collide_in_coop() {
+        // Feet on the floor at 16, or on the other player's head.
+        assert!(na::approx_eq_eps(&drop_onto_player(settings::GameMode::Race), &4.0, &0.5));
+        assert!(na::approx_eq_eps(&drop_onto_player(settings::GameMode::Coop), &-20.0, &0.5));
+    }
+
+    #[test]
+    fn playerclip_only_stops_players() {
+        let mut map = floor_map();
+        for side in map.bsp.brushes[0].sides.iter_mut() {
+            side.contents = map::bsp::CONTENTS_PLAYERCLIP;
+        }
+        let ray = map::cast::Ray {
+            orig: na::Pnt3::new(0.0, 0.0, 0.0),
+            dir: na::Vec3::new(0.0, 32.0, 0.0),
+            halfextents: na::zero(),
+        };
+        assert!(map.cast_ray(&ray, map::bsp::MASK_SOLID).is_none());
+        assert!(map.cast_ray(&ray, map::bsp::MASK_PLAYERSOLID).is_some());
+    }
+
+    #[test]
+    fn hook_pulls_in_without_overshooting() {
+        let mut game = Game::new(floor_map());
+        game.add_player();
+        let anchor = na::Pnt3::new(0.0, -300.0, 0.0);
+        game.players[0].pos = na::Pnt3::new(0.0, -150.0, 0.0);
+        game.players[0].hook = Some(anchor);
+        game.players[0].hooklength = 150.0;
+        game.players[0].flags.insert(player::PLAYER_HOLDING_HOOK);
+        let hook = MoveInput { hook: true, .. input(na::zero(), 0.0, false) };
+        for _ in 0..400 {
+            game.tick(&[hook.clone()]);
+            let pl = &game.players[0];
+            assert!(pl.pos.y > anchor.y);
+            assert!(na::norm(&(pl.pos.to_vec() - anchor.to_vec())) <= pl.hooklength + 2.0);
+        }
+        // Hanging still from the shortest rope, rather than bouncing on it.
+        let pl = &game.players[0];
+        assert!(na::approx_eq_eps(&pl.pos.y, &(anchor.y + game.movesettings.hookminlength), &1.0));
+        assert!(na::norm(&pl.vel) < 10.0);
+    }
+
+    /// `floor_map` with another solid brush in the world.
+    pub fn with_brush(brush: map::bsp::Brush) -> map::Map {
+        let mut map = floor_map();
+        map.bsp.brushes.push(brush);
+        map.bsp.leafbrushes.push(1);
+        map.bsp.leaves[0].n_leafbrushes = 2;
+        map
+    }
+
+    /// `floor_map` with another solid box in the world.
+    pub fn with_box(center: na::Pnt3<f32>, halfextents: na::Vec3<f32>) -> map::Map {
+        with_brush(map::bsp::Brush::from_box(&center, &halfextents))
+    }
+
+    /// `floor_map` with a ramp rising from z = 0 toward +z, `slope` units up for each unit along.
+    pub fn ramp_map(slope: f32) -> map::Map {
+        let side = |x: f32, y: f32, z: f32, dist: f32| map::bsp::BrushSide {
+            plane: map::bsp::Plane { norm: na::Vec3::new(x, y, z), dist: dist },
+            flags: 0,
+            contents: map::bsp::CONTENTS_SOLID,
+        };
+        let len = na::norm(&na::Vec3::new(0.0, 1.0, slope));
+        with_brush(map::bsp::Brush {
+            sides: vec![
+                side(0.0, -1.0 / len, -slope / len, -16.0 / len),
+                side(0.0, 1.0, 0.0, 16.0),
+                side(0.0, 0.0, -1.0, 0.0),
+                side(0.0, 0.0, 1.0, 1024.0),
+                side(1.0, 0.0, 0.0, 1024.0),
+                side(-1.0, 0.0, 0.0, 1024.0),
+            ]
+        })
+    }
+
+    /// `floor_map` with a tall wall whose face is at x = 32.
+    fn wall_map() -> map::Map {
+        with_box(na::Pnt3::new(64.0, -256.0, 0.0), na::Vec3::new(32.0, 272.0, 1024.0))
+    }
+
+    /// Puts the player in the air against the wall and tries to jump off it.
+    fn wall_jump(game: &mut Game) -> bool {
+        game.players[0].pos = na::Pnt3::new(32.0 - 8.5, -100.0, 0.0);
+        game.players[0].vel = na::Vec3::new(200.0, 0.0, 0.0);
+        game.tick(&[input(na::Vec3::new(100.0, 0.0, 0.0), 0.0, false)]);
+        game.tick(&[input(na::Vec3::new(100.0, 0.0, 0.0), 0.0, true)]);
+        game.players[0].vel.x < -100.0
+    }
+
+    #[test]
+    fn one_wall_jump_per_wall() {
+        let mut game = Game::new(wall_map());
+        game.add_player();
+        assert!(wall_jump(&mut game));
+        assert!(!wall_jump(&mut game));
+
+        // Landing lets the same wall be used again.
+        game.players[0].pos = na::Pnt3::new(0.0, 3.5, 0.0);
+        game.players[0].vel = na::zero();
+        for _ in 0..10 {
+            game.tick(&[input(na::zero(), 0.0, false)]);
+        }
+        assert!(wall_jump(&mut game));
+    }
+
+    #[test]
+    fn crouching_keeps_the_hull_width() {
+        let mut game = Game::new(wall_map());
+        game.movesettings.hull = player::Hull::runner();
+        game.add_player();
+        // Right up against the wall, standing on the floor.
+        game.players[0].pos = na::Pnt3::new(32.0 - 6.125, 16.0 - 8.125, 0.0);
+        game.tick(&[MoveInput { crouch: true, .. input(na::zero(), 0.0, false) }]);
+        let pl = &game.players[0];
+        assert!(pl.flags.contains(player::PLAYER_CROUCHED));
+        assert_eq!(pl.halfextents.x, 6.0);
+        assert!(!game.map.box_blocked(&pl.pos, &pl.halfextents));
+    }
+
+    #[test]
+    fn no_standing_up_under_a_low_ceiling() {
+        // 20 units of headroom: enough to crouch in, not to stand.
+        let mut game = Game::new(with_box(na::Pnt3::new(0.0, -36.0, 0.0), na::Vec3::new(1024.0, 32.0, 1024.0)));
+        let hull = game.movesettings.hull;
+        game.add_player();
+        game.players[0].set_hull(&hull.crouched());
+        game.players[0].flags.insert(player::PLAYER_CROUCHED);
+        game.players[0].pos.y = 16.0 - hull.crouched().halfextents.y - 0.125;
+        for _ in 0..10 {
+            game.tick(&[input(na::zero(), 0.0, false)]);
+        }
+        assert!(game.players[0].flags.contains(player::PLAYER_CROUCHED));
+        assert_eq!(game.players[0].halfextents, hull.crouched().halfextents);
+    }
+
+    #[test]
+    fn maps_can_pick_the_hull() {
+        let mut map = floor_map();
+        map.hull = Some("runner".to_string());
+        let mut game = Game::new(map);
+        assert_eq!(game.movesettings.hull, player::Hull::runner());
+        game.set_physics(settings::PhysicsMode::Vq3);
+        assert_eq!(game.movesettings.hull, player::Hull::runner());
+    }
+
+    /// Adds a solid box that moves the way `mover` says, returning its entity index.
+    pub fn add_mover(map: &mut map::Map, center: na::Pnt3<f32>, halfextents: na::Vec3<f32>,
+                 mover: map::mover::Mover) -> u32 {
+        let entity = add_entity(map, center, halfextents, map::EntityKind::Solid);
+        map.entities[entity as usize].mover = Some(mover);
+        entity
+    }
+
+    /// Adds a box entity of the given kind, returning its index.
+    pub fn add_entity(map: &mut map::Map, center: na::Pnt3<f32>, halfextents: na::Vec3<f32>,
+                      kind: map::EntityKind) -> u32 {
+        let brush = map.bsp.brushes.len() as u32;
+        map.bsp.brushes.push(map::bsp::Brush::from_box(&center, &halfextents));
+        map.models.push(map::Model {
+            mins: center.to_vec() - halfextents,
+            maxs: center.to_vec() + halfextents,
+            face: 0,
+            n_faces: 0,
+            brush: brush,
+            n_brushes: 1,
+        });
+        map.entities.push(map::Entity {
+            model: map.models.len() as u32 - 1,
+            kind: kind,
+            mover: None,
+        });
+        map.entities.len() as u32 - 1
+    }
+
+    #[test]
+    fn rocket_jump() {
+        let mut game = Game::new(floor_map());
+        game.add_player();
+        for _ in 0..50 {
+            game.tick(&[input(na::zero(), 0.0, false)]);
+        }
+        // Looking straight down at the floor.
+        let fire = MoveInput {
+            eyeang: na::Vec3::new(1.5, 0.0, 0.0),
+            fire: Some(weapon::WeaponKind::Rocket),
+            .. input(na::zero(), 0.0, false)
+        };
+        game.tick(&[fire]);
+        let mut fastest = 0.0f32;
+        for _ in 0..10 {
+            game.tick(&[input(na::zero(), 0.0, false)]);
+            fastest = fastest.min(game.players[0].vel.y);
+        }
+        assert!(game.projectiles.is_empty());
+        assert!(fastest < -300.0);
+        assert!(!game.players[0].flags.contains(player::PLAYER_ONGROUND));
+        assert!(game.players[0].health < game.healthsettings.maxhealth);
+    }
+
+    #[test]
+    fn blast_on_the_aim_point_pushes_up() {
+        let mut game = Game::new(floor_map());
+        game.add_player();
+        let aim = game.players[0].pos + na::Vec3::new(0.0, -game.players[0].halfextents.y, 0.0);
+        weapon::explode(&mut game, aim, weapon::WeaponKind::Plasma, 1);
+        let vel = game.players[0].vel;
+        assert!(vel.x == 0.0 && vel.z == 0.0);
+        assert!(vel.y < 0.0);
+    }
+
+    #[test]
+    fn hazards_hurt_without_blocking() {
+        let mut map = floor_map();
+        add_entity(&mut map, na::Pnt3::new(64.0, 8.0, 0.0), na::Vec3::new(16.0, 8.0, 16.0),
+                   map::EntityKind::Hazard(20.0));
+        let mut game = Game::new(map);
+        game.add_player();
+        for _ in 0..150 {
+            game.tick(&[input(na::Vec3::new(220.0, 0.0, 0.0), 0.0, false)]);
+        }
+        // Walked right through it, and got hurt on the way.
+        let pl = &game.players[0];
+        assert!(pl.pos.x > 64.0 + 16.0 + 8.0);
+        assert!(pl.health < game.healthsettings.maxhealth);
+        assert!(pl.health > 0.0);
+    }
+
+    /// Drops a player from `height` above the floor, returning how much of their health is left once they've landed.
+    fn health_after_fall(height: f32) -> f32 {
+        let mut game = Game::new(floor_map());
+        game.add_player();
+        game.players[0].pos.y = 16.0 - 12.0 - height;
+        for _ in 0..400 {
+            game.tick(&[input(na::zero(), 0.0, false)]);
+        }
+        assert!(game.players[0].flags.contains(player::PLAYER_ONGROUND));
+        game.players[0].health / game.healthsettings.maxhealth
+    }
+
+    #[test]
+    fn long_falls_hurt() {
+        assert_eq!(health_after_fall(100.0), 1.0);
+        let health = health_after_fall(600.0);
+        assert!(health < 1.0);
+        assert!(health > 0.0);
+    }
+
+    #[test]
+    fn plat_carries_rider() {
+        let mut map = floor_map();
+        let plat = add_mover(&mut map, na::Pnt3::new(0.0, 12.0, 0.0), na::Vec3::new(32.0, 4.0, 32.0),
+                             map::mover::Mover::new(map::mover::MoverKind::Plat,
+                                                    vec![na::zero(), na::Vec3::new(0.0, -64.0, 0.0)],
+                                                    64.0, -1.0));
+        let mut game = Game::new(map);
+        game.add_player();
+        game.players[0].pos.y = -10.0;
+        for _ in 0..400 {
+            game.tick(&[input(na::zero(), 0.0, false)]);
+        }
+        // Landing on the plat sends it up, and the player with it.
+        assert!(na::approx_eq_eps(&game.players[0].pos.y, &(8.0 - 64.0 - 12.0), &0.5));
+        assert_eq!(game.players[0].groundentity, Some(plat));
+    }
+
+    #[test]
+    fn player_blocks_closing_door() {
+        let mut map = floor_map();
+        let door = add_mover(&mut map, na::Pnt3::new(0.0, -40.0, 0.0), na::Vec3::new(32.0, 8.0, 32.0),
+                             map::mover::Mover::new(map::mover::MoverKind::Door,
+                                                    vec![na::zero(), na::Vec3::new(0.0, 40.0, 0.0)],
+                                                    64.0, 1.0));
+        let mut game = Game::new(map);
+        game.add_player();
+        for _ in 0..50 {
+            game.tick(&[input(na::zero(), 0.0, false)]);
+        }
+        let standing = game.players[0].pos.y;
+
+        if let Some(ref mut mover) = game.map.entities[door as usize].mover {
+            mover.trigger(game.time);
+        }
+        for _ in 0..400 {
+            game.tick(&[input(na::zero(), 0.0, false)]);
+            let pl = &game.players[0];
+            assert!(!game.map.entity_overlaps(door, &pl.pos, &pl.halfextents));
+        }
+        // The door came down on the player's head and went back up, rather than crushing them.
+        assert!(na::approx_eq_eps(&game.players[0].pos.y, &standing, &0.01));
+        assert!(na::approx_eq(&game.map.entities[door as usize].offset(), &na::zero()));
+    }
+
+    #[test]
+    fn maxfps_uses_whole_milliseconds() {
+        let mut game = Game::new(floor_map());
+        game.movesettings = settings::MoveSettings::vq3();
+        assert_eq!(game.tick_interval(), 0.008);
+        game.movesettings.maxfps = 333;
+        assert_eq!(game.tick_interval(), 0.003);
+    }
+
+    /// Jumps, then jumps again as soon as that one lands, returning how high each went.
+    fn jump_heights(mode: settings::PhysicsMode) -> (f32, f32) {
+        let mut game = Game::new(floor_map());
+        game.set_physics(mode);
+        game.add_player();
+        for _ in 0..50 {
+            game.tick(&[input(na::zero(), 0.0, false)]);
+        }
+        let floor = game.players[0].pos.y;
+        let mut heights = vec![];
+        for _ in 0..2 {
+            game.tick(&[input(na::zero(), 0.0, true)]);
+            let mut top = floor;
+            for _ in 0..400 {
+                game.tick(&[input(na::zero(), 0.0, false)]);
+                top = top.min(game.players[0].pos.y);
+                if game.players[0].flags.contains(player::PLAYER_ONGROUND) {
+                    break;
+                }
+            }
+            heights.push(floor - top);
+        }
+        (heights[0], heights[1])
+    }
+
+    #[test]
+    fn double_jumps_go_higher() {
+        let (first, second) = jump_heights(settings::PhysicsMode::Cpm);
+        assert!(second > first + 20.0);
+        let (first, second) = jump_heights(settings::PhysicsMode::Vq3);
+        assert!(na::approx_eq_eps(&first, &second, &2.0));
+    }
+
+    /// Runs up a 30 degree ramp and jumps off it, returning the upward speed it left with.
+    fn ramp_jump_speed(rampjump: bool) -> f32 {
+        let mut game = Game::new(ramp_map(0.577));
+        game.movesettings.rampjump = rampjump;
+        game.add_player();
+        game.players[0].pos.z = -100.0;
+        for _ in 0..50 {
+            game.tick(&[input(na::zero(), 0.0, false)]);
+        }
+        let run = na::Vec3::new(0.0, 0.0, 220.0);
+        for _ in 0..300 {
+            let onramp = game.players[0].pos.z > 60.0 &&
+                game.players[0].flags.contains(player::PLAYER_ONGROUND);
+            game.tick(&[input(run, 0.0, onramp)]);
+            if onramp {
+                return game.players[0].vel.y;
+            }
+        }
+        panic!("never got up the ramp");
+    }
+
+    #[test]
+    fn ramp_jumps_keep_the_climb() {
+        assert!(ramp_jump_speed(true) < ramp_jump_speed(false) - 50.0);
+    }
+
+    #[test]
+    fn walking_off_a_low_ledge_stays_on_the_ground() {
+        // A 2 unit ledge at x = 0, lower than a step.
+        let mut game = Game::new(with_box(na::Pnt3::new(-512.0, 15.0, 0.0), na::Vec3::new(512.0, 1.0, 1024.0)));
+        game.add_player();
+        for _ in 0..50 {
+            game.tick(&[input(na::zero(), 0.0, false)]);
+        }
+        for _ in 0..100 {
+            game.tick(&[input(na::Vec3::new(220.0, 0.0, 0.0), 0.0, false)]);
+            assert!(game.players[0].flags.contains(player::PLAYER_ONGROUND));
+        }
+        assert!(game.players[0].pos.x > 8.0);
+        assert!(na::approx_eq_eps(&game.players[0].pos.y, &4.0, &0.5));
+    }
+
+    /// Drops onto a 60 degree ramp and strafes across it, returning the sideways speed gained.
+    fn surf_gain(surf: bool) -> f32 {
+        let slope = 1.732;
+        let mut game = Game::new(ramp_map(slope));
+        game.movesettings.surf = surf;
+        game.add_player();
+        game.players[0].pos = na::Pnt3::new(0.0, 16.0 - slope * 308.0 - 14.0, 300.0);
+        for _ in 0..20 {
+            game.tick(&[input(na::zero(), 0.0, false)]);
+        }
+        for _ in 0..3 {
+            game.tick(&[input(na::Vec3::new(220.0, 0.0, 0.0), 0.0, false)]);
+        }
+        assert!(game.players[0].flags.contains(player::PLAYER_ONRAMP));
+        game.players[0].vel.x
+    }
+
+    #[test]
+    fn surfing_strafes_hard() {
+        assert!(surf_gain(true) > 50.0);
+        assert!(surf_gain(false) < 30.0);
+    }
+
+    #[test]
+    fn ladders_climb_up_and_down() {
+        // A ladder half a unit in front of the player.
+        let mut map = with_box(na::Pnt3::new(0.0, -100.0, -24.5), na::Vec3::new(64.0, 116.0, 16.0));
+        for side in map.bsp.brushes[1].sides.iter_mut() {
+            side.flags = map::bsp::SURF_LADDER;
+        }
+        let mut game = Game::new(map);
+        game.add_player();
+        let forward = na::Vec3::new(0.0, 0.0, -220.0);
+        for _ in 0..50 {
+            game.tick(&[input(forward, 0.0, false)]);
+        }
+        assert!(game.players[0].flags.contains(player::PLAYER_ONLADDER));
+        let top = game.players[0].pos.y;
+        assert!(top < -50.0);
 
+        // Looking down climbs back down.
+        let down = MoveInput { eyeang: na::Vec3::new(1.0, 0.0, 0.0), .. input(forward, 0.0, false) };
+        for _ in 0..20 {
+            game.tick(&[down.clone()]);
+        }
+        assert!(game.players[0].pos.y > top + 20.0);
+    }
 }
@@ -0,0 +1,156 @@
+use na;
+use std::collections::VecDeque;
+use player::PlayerState;
+use super::PlayerSnapshot;
+
+/// How many snapshots to keep for each player.
+const ENTITY_HISTORY_LENGTH: usize = 32;
+/// If the server's clock looks this far off from where we thought, jump to it instead of easing.
+const CLOCK_RESYNC: f64 = 0.25;
+
+/// Where one player was at each snapshot, oldest first.
+struct EntityHistory {
+    states: VecDeque<(f64, PlayerState)>,
+}
+impl EntityHistory {
+    fn new() -> EntityHistory {
+        EntityHistory {
+            states: VecDeque::new(),
+        }
+    }
+
+    fn push(&mut self, time: f64, state: PlayerState) {
+        // Anything arriving out of order is too late to be useful.
+        if self.states.back().map(|&(t, _)| t >= time).unwrap_or(false) {
+            return;
+        }
+        if self.states.len() >= ENTITY_HISTORY_LENGTH {
+            self.states.pop_front();
+        }
+        self.states.push_back((time, state));
+    }
+
+    fn sample(&self, time: f64, maxextrapolation: f64) -> Option<PlayerState> {
+        let mut prev: Option<(f64, PlayerState)> = None;
+        for &(t, state) in &self.states {
+            if t >= time {
+                return Some(match prev {
+                    Some((prevt, prevstate)) => prevstate.lerp(&state, ((time - prevt) / (t - prevt)) as f32),
+                    None => state
+                });
+            }
+            prev = Some((t, state));
+        }
+        // Ran out of snapshots, so carry on in a straight line for a little while.
+        prev.map(|(t, state)| {
+            let ahead = na::clamp(time - t, 0.0, maxextrapolation) as f32;
+            PlayerState {
+                pos: state.pos + state.vel * ahead,
+                ..state
+            }
+        })
+    }
+}
+
+/// Draws remote players a little in the past, blending between snapshots we already have,
+/// so they move smoothly however the packets arrive.
+pub struct Interpolation {
+    /// Each player's history, by id.
+    entities: Vec<(u32, EntityHistory)>,
+    /// How far behind the server remote players are drawn, in seconds.
+    pub delay: f64,
+    /// How far past the newest snapshot a player can be guessed at, in seconds.
+    pub maxextrapolation: f64,
+    /// Server time minus local time, smoothed over snapshots.
+    clockoffset: Option<f64>,
+}
+impl Interpolation {
+    pub fn new(delay: f64, maxextrapolation: f64) -> Interpolation {
+        Interpolation {
+            entities: vec![],
+            delay: delay,
+            maxextrapolation: maxextrapolation,
+            clockoffset: None,
+        }
+    }
+
+    /// Records a snapshot taken at `servertime` that arrived at `localtime`.
+    pub fn push(&mut self, servertime: f64, localtime: f64, players: &[PlayerSnapshot]) {
+        // Forget anyone who's left, and start afresh for anyone who's joined.
+        self.entities.retain(|&(id, _)| players.iter().any(|pl| pl.id == id));
+        for pl in players {
+            if !self.entities.iter().any(|&(id, _)| id == pl.id) {
+                self.entities.push((pl.id, EntityHistory::new()));
+            }
+            let entity = self.entities.iter_mut().find(|entity| entity.0 == pl.id).unwrap();
+            entity.1.push(servertime, pl.state());
+        }
+
+        let offset = servertime - localtime;
+        self.clockoffset = Some(match self.clockoffset {
+            Some(old) if (offset - old).abs() < CLOCK_RESYNC => old + (offset - old) * 0.05,
+            _ => offset
+        });
+    }
+
+    /// The server time remote players should be drawn at, at `localtime`.
+    pub fn render_time(&self, localtime: f64) -> f64 {
+        localtime + self.clockoffset.unwrap_or(0.0) - self.delay
+    }
+
+    /// Where the player with the given id was at server time `time`, if we've heard about them.
+    pub fn sample(&self, id: u32, time: f64) -> Option<PlayerState> {
+        self.entities.iter()
+            .find(|&&(entityid, _)| entityid == id)
+            .and_then(|&(_, ref entity)| entity.sample(time, self.maxextrapolation))
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use na;
+    use net::PlayerSnapshot;
+    use super::Interpolation;
+
+    fn at(x: f32, velx: f32) -> PlayerSnapshot {
+        let mut pl = PlayerSnapshot::baseline();
+        pl.pos.x = x;
+        pl.vel.x = velx;
+        pl
+    }
+
+    #[test]
+    fn blends_between_snapshots() {
+        let mut interp = Interpolation::new(0.1, 0.05);
+        interp.push(1.0, 5.0, &[at(0.0, 100.0)]);
+        interp.push(1.1, 5.1, &[at(10.0, 100.0)]);
+        let state = interp.sample(0, 1.025).unwrap();
+        assert!(na::approx_eq_eps(&state.pos.x, &2.5, &0.001));
+        // The clock offset is 4 seconds behind local time, less the delay.
+        assert!(na::approx_eq_eps(&interp.render_time(5.1), &1.0, &0.001));
+    }
+
+    #[test]
+    fn extrapolation_is_limited() {
+        let mut interp = Interpolation::new(0.1, 0.05);
+        interp.push(1.0, 1.0, &[at(0.0, 100.0)]);
+        let state = interp.sample(0, 1.02).unwrap();
+        assert!(na::approx_eq_eps(&state.pos.x, &2.0, &0.001));
+        let state = interp.sample(0, 2.0).unwrap();
+        assert!(na::approx_eq_eps(&state.pos.x, &5.0, &0.001));
+        assert!(interp.sample(1, 1.0).is_none());
+    }
+
+    #[test]
+    fn players_keep_their_history_when_others_leave() {
+        let mut interp = Interpolation::new(0.1, 0.05);
+        let mut second = at(100.0, 0.0);
+        second.id = 1;
+        interp.push(1.0, 1.0, &[at(0.0, 0.0), second]);
+        second.pos.x = 110.0;
+        interp.push(1.1, 1.1, &[second]);
+        let state = interp.sample(1, 1.05).unwrap();
+        assert!(na::approx_eq_eps(&state.pos.x, &105.0, &0.001));
+        assert!(interp.sample(0, 1.05).is_none());
+    }
+}
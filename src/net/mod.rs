@@ -0,0 +1,744 @@
+use na;
+use byteorder::{self, LittleEndian, ReadBytesExt, WriteBytesExt};
+use std;
+use std::io::{self, Cursor};
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::collections::VecDeque;
+use player::Player;
+use player::movement::MoveInput;
+use settings::PhysicsMode;
+use map::mover::MoverState;
+use Game;
+use weapon::{Projectile, WeaponKind};
+
+pub use self::snapshot::{PlayerSnapshot, Precision, History};
+pub use self::interp::Interpolation;
+
+pub mod interp;
+pub mod snapshot;
+
+pub const DEFAULT_PORT: u16 = 27960;
+/// Bumped whenever the message format changes, so old clients get turned away.
+pub const PROTOCOL_VERSION: u32 = 5;
+/// Nothing we send should be bigger than this, to stay clear of fragmentation.
+pub const MAX_PACKET: usize = 1400;
+/// The most players a server takes: as many as fit in one packet with every field sent at full precision,
+/// so even a snapshot with nothing to delta against gets through.
+pub const MAX_CLIENTS: usize = 8;
+/// The most projectiles a snapshot carries. Fewer go if there isn't room left after the players and movers.
+pub const MAX_SNAPSHOT_PROJECTILES: usize = 16;
+
+#[derive(Debug)]
+pub enum NetError {
+    ByteOrderError(byteorder::Error),
+    BadMessage(u8),
+    /// A snapshot was delta-encoded against one we no longer have.
+    MissingBase(u64),
+    /// Bigger than `MAX_PACKET`, so it may have been cut short.
+    Oversized(usize),
+}
+impl std::convert::From<byteorder::Error> for NetError {
+    fn from(e: byteorder::Error) -> NetError {
+        NetError::ByteOrderError(e)
+    }
+}
+
+pub enum ClientMessage {
+    Connect { version: u32 },
+    /// One tick's worth of input, numbered so the server can say which it's seen.
+    /// `snapack` is the newest snapshot we've received, for the server to delta against.
+    Input { sequence: u32, snapack: u64, input: MoveInput },
+    Disconnect,
+}
+
+pub enum ServerMessage {
+    /// Sent in reply to `Connect`. Together these decide how long a tick is.
+    Welcome { physics: PhysicsMode, tickrate: u32 },
+    Snapshot(Snapshot),
+}
+
+/// Where one of the map's movers is, and what it's doing.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct MoverSnapshot {
+    /// Index of the entity the mover drives.
+    pub entity: u32,
+    pub offset: na::Vec3<f32>,
+    pub state: MoverState,
+}
+impl MoverSnapshot {
+    /// Every mover in the map, in entity order.
+    pub fn from_map(map: &::map::Map) -> Vec<MoverSnapshot> {
+        map.entities.iter().enumerate()
+            .filter_map(|(idx, entity)| entity.mover.as_ref().map(|mover| MoverSnapshot {
+                entity: idx as u32,
+                offset: mover.offset,
+                state: mover.state,
+            }))
+            .collect()
+    }
+}
+
+/// The state of the game after one server tick.
+pub struct Snapshot {
+    pub tick: u64,
+    /// The game time after the tick.
+    pub time: f32,
+    /// The earlier snapshot `players` is delta-encoded against, or 0 for none.
+    pub basetick: u64,
+    pub precision: Precision,
+    /// The last input the server ran for the client this is sent to.
+    pub ack: u32,
+    /// The id of the player the client this is sent to controls.
+    pub you: u32,
+    pub players: Vec<PlayerSnapshot>,
+    /// Everything in flight. Sent whole, since they don't live long enough to delta against.
+    /// Only the newest that fit in the packet are sent.
+    pub projectiles: Vec<Projectile>,
+    /// Sent whole too, since there are only ever a few. Those that don't fit in the packet are left out.
+    pub movers: Vec<MoverSnapshot>,
+}
+impl Snapshot {
+    /// Makes `game` look the way it did on the server, returning the index of the player it was sent to.
+    /// Players are matched up by id, so anything the snapshot doesn't cover, like view punch, carries over.
+    pub fn apply(&self, game: &mut Game) -> Option<usize> {
+        let mut old = std::mem::replace(&mut game.players, vec![]);
+        for state in &self.players {
+            let mut pl = match old.iter().position(|pl| pl.id == state.id) {
+                Some(idx) => old.swap_remove(idx),
+                None => Player::new(&game.movesettings.hull)
+            };
+            state.apply(&mut pl);
+            game.players.push(pl);
+        }
+        game.projectiles = self.projectiles.clone();
+        for snap in &self.movers {
+            if let Some(entity) = game.map.entities.get_mut(snap.entity as usize) {
+                if let Some(ref mut mover) = entity.mover {
+                    mover.offset = snap.offset;
+                    mover.state = snap.state;
+                    mover.delta = na::zero();
+                }
+            }
+        }
+        game.tickcount = self.tick;
+        game.time = self.time;
+        game.player_index(self.you)
+    }
+}
+
+const MSG_CONNECT: u8 = 1;
+const MSG_INPUT: u8 = 2;
+const MSG_DISCONNECT: u8 = 3;
+const MSG_WELCOME: u8 = 4;
+const MSG_SNAPSHOT: u8 = 5;
+
+const INPUT_JUMP: u8 = 0b00_01;
+const INPUT_CROUCH: u8 = 0b00_10;
+const INPUT_HOOK: u8 = 0b01_00;
+const INPUT_RESET: u8 = 0b10_00;
+
+fn write_vec(buf: &mut Vec<u8>, v: &na::Vec3<f32>) {
+    buf.write_f32::<LittleEndian>(v.x).unwrap();
+    buf.write_f32::<LittleEndian>(v.y).unwrap();
+    buf.write_f32::<LittleEndian>(v.z).unwrap();
+}
+
+fn read_vec(cursor: &mut Cursor<&[u8]>) -> byteorder::Result<na::Vec3<f32>> {
+    let x = try!(cursor.read_f32::<LittleEndian>());
+    let y = try!(cursor.read_f32::<LittleEndian>());
+    let z = try!(cursor.read_f32::<LittleEndian>());
+    Ok(na::Vec3::new(x, y, z))
+}
+
+fn write_weapon(buf: &mut Vec<u8>, kind: Option<WeaponKind>) {
+    buf.write_u8(match kind {
+        None => 0,
+        Some(WeaponKind::Rocket) => 1,
+        Some(WeaponKind::Plasma) => 2,
+    }).unwrap();
+}
+
+fn read_weapon(cursor: &mut Cursor<&[u8]>) -> Result<Option<WeaponKind>, NetError> {
+    match try!(cursor.read_u8()) {
+        0 => Ok(None),
+        1 => Ok(Some(WeaponKind::Rocket)),
+        2 => Ok(Some(WeaponKind::Plasma)),
+        n => Err(NetError::BadMessage(n))
+    }
+}
+
+fn write_projectile(buf: &mut Vec<u8>, proj: &Projectile) {
+    write_weapon(buf, Some(proj.kind));
+    buf.write_u32::<LittleEndian>(proj.owner).unwrap();
+    write_vec(buf, &proj.pos.to_vec());
+    write_vec(buf, &proj.vel);
+    buf.write_f32::<LittleEndian>(proj.spawntime).unwrap();
+}
+
+fn read_projectile(cursor: &mut Cursor<&[u8]>) -> Result<Projectile, NetError> {
+    let kind = match try!(read_weapon(cursor)) {
+        Some(kind) => kind,
+        None => return Err(NetError::BadMessage(0))
+    };
+    Ok(Projectile {
+        kind: kind,
+        owner: try!(cursor.read_u32::<LittleEndian>()),
+        pos: try!(read_vec(cursor)).to_pnt(),
+        vel: try!(read_vec(cursor)),
+        spawntime: try!(cursor.read_f32::<LittleEndian>()),
+    })
+}
+
+fn write_mover(buf: &mut Vec<u8>, mover: &MoverSnapshot) {
+    buf.write_u32::<LittleEndian>(mover.entity).unwrap();
+    write_vec(buf, &mover.offset);
+    match mover.state {
+        MoverState::Waiting { stop, until } => {
+            buf.write_u8(0).unwrap();
+            buf.write_u32::<LittleEndian>(stop as u32).unwrap();
+            buf.write_f32::<LittleEndian>(until).unwrap();
+        },
+        MoverState::Moving { from, to, progress } => {
+            buf.write_u8(1).unwrap();
+            buf.write_u32::<LittleEndian>(from as u32).unwrap();
+            buf.write_u32::<LittleEndian>(to as u32).unwrap();
+            buf.write_f32::<LittleEndian>(progress).unwrap();
+        },
+    }
+}
+
+fn read_mover(cursor: &mut Cursor<&[u8]>) -> Result<MoverSnapshot, NetError> {
+    let entity = try!(cursor.read_u32::<LittleEndian>());
+    let offset = try!(read_vec(cursor));
+    let state = match try!(cursor.read_u8()) {
+        0 => MoverState::Waiting {
+            stop: try!(cursor.read_u32::<LittleEndian>()) as usize,
+            until: try!(cursor.read_f32::<LittleEndian>()),
+        },
+        1 => MoverState::Moving {
+            from: try!(cursor.read_u32::<LittleEndian>()) as usize,
+            to: try!(cursor.read_u32::<LittleEndian>()) as usize,
+            progress: try!(cursor.read_f32::<LittleEndian>()),
+        },
+        n => return Err(NetError::BadMessage(n))
+    };
+    Ok(MoverSnapshot { entity: entity, offset: offset, state: state })
+}
+
+/// Encodes `items` one at a time until one doesn't fit in the `room` left, which is used up as they go.
+fn encode_while_room<T, F: Fn(&mut Vec<u8>, &T)>(items: &[T], room: &mut usize, write: F) -> Vec<Vec<u8>> {
+    let mut encoded = vec![];
+    for item in items {
+        let mut buf = vec![];
+        write(&mut buf, item);
+        if buf.len() > *room {
+            break;
+        }
+        *room -= buf.len();
+        encoded.push(buf);
+    }
+    encoded
+}
+
+fn write_input(buf: &mut Vec<u8>, input: &MoveInput) {
+    write_vec(buf, &input.wishvel);
+    write_vec(buf, &input.eyeang);
+    let mut buttons = 0;
+    if input.jump { buttons |= INPUT_JUMP; }
+    if input.crouch { buttons |= INPUT_CROUCH; }
+    if input.hook { buttons |= INPUT_HOOK; }
+    if input.reset { buttons |= INPUT_RESET; }
+    buf.write_u8(buttons).unwrap();
+    write_weapon(buf, input.fire);
+}
+
+fn read_input(cursor: &mut Cursor<&[u8]>) -> Result<MoveInput, NetError> {
+    let wishvel = try!(read_vec(cursor));
+    let eyeang = try!(read_vec(cursor));
+    let buttons = try!(cursor.read_u8());
+    let fire = try!(read_weapon(cursor));
+    Ok(MoveInput {
+        wishvel: wishvel,
+        eyeang: eyeang,
+        jump: buttons & INPUT_JUMP != 0,
+        crouch: buttons & INPUT_CROUCH != 0,
+        hook: buttons & INPUT_HOOK != 0,
+        reset: buttons & INPUT_RESET != 0,
+        fire: fire,
+    })
+}
+
+impl ClientMessage {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf = vec![];
+        match *self {
+            ClientMessage::Connect { version } => {
+                buf.push(MSG_CONNECT);
+                buf.write_u32::<LittleEndian>(version).unwrap();
+            },
+            ClientMessage::Input { sequence, snapack, ref input } => {
+                buf.push(MSG_INPUT);
+                buf.write_u32::<LittleEndian>(sequence).unwrap();
+                buf.write_u64::<LittleEndian>(snapack).unwrap();
+                write_input(&mut buf, input);
+            },
+            ClientMessage::Disconnect => {
+                buf.push(MSG_DISCONNECT);
+            },
+        }
+        buf
+    }
+
+    pub fn decode(data: &[u8]) -> Result<ClientMessage, NetError> {
+        if data.len() > MAX_PACKET {
+            return Err(NetError::Oversized(data.len()));
+        }
+        let mut cursor = Cursor::new(data);
+        match try!(cursor.read_u8()) {
+            MSG_CONNECT => {
+                let version = try!(cursor.read_u32::<LittleEndian>());
+                Ok(ClientMessage::Connect { version: version })
+            },
+            MSG_INPUT => {
+                let sequence = try!(cursor.read_u32::<LittleEndian>());
+                let snapack = try!(cursor.read_u64::<LittleEndian>());
+                let input = try!(read_input(&mut cursor));
+                Ok(ClientMessage::Input { sequence: sequence, snapack: snapack, input: input })
+            },
+            MSG_DISCONNECT => Ok(ClientMessage::Disconnect),
+            n => Err(NetError::BadMessage(n))
+        }
+    }
+}
+
+impl ServerMessage {
+    /// Snapshots are delta-encoded against their `basetick`, which has to be in `history`.
+    /// They always fit in `MAX_PACKET`, as long as there are no more than `MAX_CLIENTS` players.
+    pub fn encode(&self, history: &History) -> Vec<u8> {
+        let mut buf = vec![];
+        match *self {
+            ServerMessage::Welcome { physics, tickrate } => {
+                buf.push(MSG_WELCOME);
+                buf.write_u8(match physics {
+                    PhysicsMode::Vel0city => 0,
+                    PhysicsMode::Vq3 => 1,
+                    PhysicsMode::Cpm => 2,
+                }).unwrap();
+                buf.write_u32::<LittleEndian>(tickrate).unwrap();
+            },
+            ServerMessage::Snapshot(ref snap) => {
+                buf.push(MSG_SNAPSHOT);
+                buf.write_u64::<LittleEndian>(snap.tick).unwrap();
+                buf.write_f32::<LittleEndian>(snap.time).unwrap();
+                buf.write_u64::<LittleEndian>(snap.basetick).unwrap();
+                buf.write_u8(match snap.precision {
+                    Precision::Full => 0,
+                    Precision::Quantized => 1,
+                }).unwrap();
+                buf.write_u32::<LittleEndian>(snap.ack).unwrap();
+                buf.write_u32::<LittleEndian>(snap.you).unwrap();
+                let base = history.get(snap.basetick).unwrap_or(&[]);
+                snapshot::write_players(&mut buf, base, &snap.players, snap.precision);
+
+                // Then whatever else fits: movers first, since prediction needs them, then the newest projectiles.
+                // Three bytes are kept back for the counts.
+                let mut room = MAX_PACKET.saturating_sub(buf.len() + 3);
+                let movers = encode_while_room(&snap.movers, &mut room, write_mover);
+                let newest: Vec<_> = snap.projectiles.iter().rev().take(MAX_SNAPSHOT_PROJECTILES).cloned().collect();
+                let mut projectiles = encode_while_room(&newest, &mut room, write_projectile);
+                projectiles.reverse();
+                buf.write_u16::<LittleEndian>(movers.len() as u16).unwrap();
+                for mover in &movers {
+                    buf.extend(mover.iter().cloned());
+                }
+                buf.write_u8(projectiles.len() as u8).unwrap();
+                for proj in &projectiles {
+                    buf.extend(proj.iter().cloned());
+                }
+            },
+        }
+        buf
+    }
+
+    pub fn decode(data: &[u8], history: &History) -> Result<ServerMessage, NetError> {
+        if data.len() > MAX_PACKET {
+            return Err(NetError::Oversized(data.len()));
+        }
+        let mut cursor = Cursor::new(data);
+        match try!(cursor.read_u8()) {
+            MSG_WELCOME => {
+                let physics = match try!(cursor.read_u8()) {
+                    0 => PhysicsMode::Vel0city,
+                    1 => PhysicsMode::Vq3,
+                    2 => PhysicsMode::Cpm,
+                    n => return Err(NetError::BadMessage(n))
+                };
+                let tickrate = try!(cursor.read_u32::<LittleEndian>());
+                Ok(ServerMessage::Welcome { physics: physics, tickrate: tickrate })
+            },
+            MSG_SNAPSHOT => {
+                let tick = try!(cursor.read_u64::<LittleEndian>());
+                let time = try!(cursor.read_f32::<LittleEndian>());
+                let basetick = try!(cursor.read_u64::<LittleEndian>());
+                let precision = match try!(cursor.read_u8()) {
+                    0 => Precision::Full,
+                    1 => Precision::Quantized,
+                    n => return Err(NetError::BadMessage(n))
+                };
+                let ack = try!(cursor.read_u32::<LittleEndian>());
+                let you = try!(cursor.read_u32::<LittleEndian>());
+                let base = if basetick == 0 {
+                    &[][..]
+                } else {
+                    match history.get(basetick) {
+                        Some(base) => base,
+                        None => return Err(NetError::MissingBase(basetick))
+                    }
+                };
+                let players = try!(snapshot::read_players(&mut cursor, base, precision));
+                let n_movers = try!(cursor.read_u16::<LittleEndian>());
+                let mut movers = vec![];
+                for _ in 0..n_movers {
+                    movers.push(try!(read_mover(&mut cursor)));
+                }
+                let n_projectiles = try!(cursor.read_u8());
+                let mut projectiles = vec![];
+                for _ in 0..n_projectiles {
+                    projectiles.push(try!(read_projectile(&mut cursor)));
+                }
+                Ok(ServerMessage::Snapshot(Snapshot {
+                    tick: tick,
+                    time: time,
+                    basetick: basetick,
+                    precision: precision,
+                    ack: ack,
+                    you: you,
+                    players: players,
+                    projectiles: projectiles,
+                    movers: movers,
+                }))
+            },
+            n => Err(NetError::BadMessage(n))
+        }
+    }
+}
+
+/// Reads packets off `socket` on another thread, so the game loop never blocks on the network.
+pub fn spawn_receiver(socket: &UdpSocket) -> io::Result<Receiver<(Vec<u8>, SocketAddr)>> {
+    let socket = try!(socket.try_clone());
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        // One byte spare, so anything too big shows up as too big rather than quietly cut short.
+        let mut buf = [0; MAX_PACKET + 1];
+        loop {
+            match socket.recv_from(&mut buf) {
+                Ok((len, from)) => {
+                    if tx.send((buf[..len].to_vec(), from)).is_err() {
+                        break;
+                    }
+                },
+                Err(_) => break
+            }
+        }
+    });
+    Ok(rx)
+}
+
+/// A client's link to a server.
+pub struct Connection {
+    socket: UdpSocket,
+    server: SocketAddr,
+    incoming: Receiver<(Vec<u8>, SocketAddr)>,
+    /// Number of the next input to send.
+    pub sequence: u32,
+    /// Snapshots we've received, for decoding the ones delta-encoded against them.
+    history: History,
+    /// The newest snapshot we've received.
+    snapack: u64,
+    /// Packets from the server we couldn't make sense of.
+    pub badpackets: u32,
+}
+impl Connection {
+    pub fn connect<A: ToSocketAddrs>(addr: A) -> io::Result<Connection> {
+        let server = match try!(addr.to_socket_addrs()).next() {
+            Some(server) => server,
+            None => return Err(io::Error::new(io::ErrorKind::InvalidInput, "no address to connect to"))
+        };
+        let socket = try!(UdpSocket::bind("0.0.0.0:0"));
+        let incoming = try!(spawn_receiver(&socket));
+        let conn = Connection {
+            socket: socket,
+            server: server,
+            incoming: incoming,
+            sequence: 1,
+            history: History::new(),
+            snapack: 0,
+            badpackets: 0,
+        };
+        try!(conn.send(&ClientMessage::Connect { version: PROTOCOL_VERSION }));
+        Ok(conn)
+    }
+
+    pub fn send(&self, msg: &ClientMessage) -> io::Result<()> {
+        try!(self.socket.send_to(&msg.encode(), &self.server));
+        Ok(())
+    }
+
+    /// Sends one tick's input, returning its sequence number.
+    /// Failures are ignored, since the server copes with missing input anyway.
+    pub fn send_input(&mut self, input: &MoveInput) -> u32 {
+        let sequence = self.sequence;
+        self.sequence += 1;
+        let _ = self.send(&ClientMessage::Input {
+            sequence: sequence,
+            snapack: self.snapack,
+            input: input.clone()
+        });
+        sequence
+    }
+
+    /// Everything the server has sent since last time. Strangers are ignored,
+    /// and garbage is only counted in `badpackets`.
+    pub fn poll(&mut self) -> Vec<ServerMessage> {
+        let mut msgs = vec![];
+        while let Ok((data, from)) = self.incoming.try_recv() {
+            if from != self.server {
+                continue;
+            }
+            match ServerMessage::decode(&data, &self.history) {
+                Ok(ServerMessage::Snapshot(snap)) => {
+                    // Late arrivals are still worth decoding against, but don't move the ack back.
+                    if snap.tick > self.snapack {
+                        self.snapack = snap.tick;
+                    }
+                    self.history.push(snap.tick, snap.players.clone());
+                    msgs.push(ServerMessage::Snapshot(snap));
+                },
+                Ok(msg) => msgs.push(msg),
+                Err(_) => self.badpackets += 1
+            }
+        }
+        msgs
+    }
+}
+impl Drop for Connection {
+    fn drop(&mut self) {
+        let _ = self.send(&ClientMessage::Disconnect);
+    }
+}
+
+/// Runs our own player ahead of the server, so input takes effect straight away.
+pub struct Prediction {
+    /// Inputs the server hasn't run yet, with where each one left us.
+    pending: VecDeque<(u32, MoveInput, PlayerSnapshot)>,
+    /// How many times the server has disagreed with us.
+    pub mispredictions: u32,
+}
+impl Prediction {
+    pub fn new() -> Prediction {
+        Prediction {
+            pending: VecDeque::new(),
+            mispredictions: 0,
+        }
+    }
+
+    /// Runs one input locally, remembering it in case it needs replaying.
+    pub fn predict(&mut self, game: &mut Game, playeridx: u32, sequence: u32, input: MoveInput) {
+        predict_tick(game, playeridx, &input);
+        let state = PlayerSnapshot::new(&game.players[playeridx as usize]);
+        self.pending.push_back((sequence, input, state));
+    }
+
+    /// Puts `game` back the way the server had it, then replays every input the server hasn't run yet.
+    /// Returns the index of our player, if the server has one for us.
+    pub fn reconcile(&mut self, game: &mut Game, snap: &Snapshot) -> Option<usize> {
+        let playeridx = match snap.apply(game) {
+            Some(playeridx) => playeridx,
+            None => return None
+        };
+        loop {
+            let sequence = match self.pending.front() {
+                Some(&(sequence, _, _)) => sequence,
+                None => break
+            };
+            if sequence < snap.ack {
+                self.pending.pop_front();
+            } else {
+                break;
+            }
+        }
+
+        let agrees = match self.pending.front() {
+            Some(&(sequence, _, ref predicted)) if sequence == snap.ack => {
+                predicted.matches(&PlayerSnapshot::new(&game.players[playeridx]))
+            },
+            _ => true
+        };
+        if self.pending.front().map(|&(sequence, _, _)| sequence) == Some(snap.ack) {
+            self.pending.pop_front();
+        }
+        if !agrees {
+            self.mispredictions += 1;
+        }
+
+        // The view already got shaken the first time round.
+        let (viewpunch, viewpunch_vel) = {
+            let pl = &game.players[playeridx];
+            (pl.viewpunch, pl.viewpunch_vel)
+        };
+        for &mut (_, ref input, ref mut predicted) in self.pending.iter_mut() {
+            predict_tick(game, playeridx as u32, input);
+            *predicted = PlayerSnapshot::new(&game.players[playeridx]);
+        }
+        let pl = &mut game.players[playeridx];
+        pl.viewpunch = viewpunch;
+        pl.viewpunch_vel = viewpunch_vel;
+        // These already happened too.
+        pl.events.clear();
+        Some(playeridx)
+    }
+}
+
+/// One server tick, with only our own player's input.
+fn predict_tick(game: &mut Game, playeridx: u32, input: &MoveInput) {
+    let dt = game.begin_tick();
+    game.tick_player(playeridx, input, dt);
+    game.end_tick(dt);
+}
+
+#[cfg(test)]
+pub mod test {
+    use na;
+    use std::collections::VecDeque;
+    use Game;
+    use map;
+    use test::{add_mover, floor_map, input};
+    use map::mover::MoverState;
+    use weapon::{Projectile, WeaponKind};
+    use super::snapshot::test::moving_player;
+    use super::{
+        History,
+        MAX_CLIENTS,
+        MAX_PACKET,
+        MAX_SNAPSHOT_PROJECTILES,
+        MoverSnapshot,
+        PlayerSnapshot,
+        Precision,
+        Prediction,
+        ServerMessage,
+        Snapshot,
+    };
+
+    fn snapshot(game: &Game, ack: u32) -> Snapshot {
+        Snapshot {
+            tick: game.tickcount,
+            time: game.time,
+            basetick: 0,
+            precision: Precision::Full,
+            ack: ack,
+            you: game.players[0].id,
+            players: game.players.iter().map(PlayerSnapshot::new).collect(),
+            projectiles: game.projectiles.clone(),
+            movers: MoverSnapshot::from_map(&game.map),
+        }
+    }
+
+    #[test]
+    fn worst_case_snapshot_fits_in_a_packet() {
+        // Full precision with nothing to delta against, and more projectiles and movers than there's room for.
+        let players: Vec<_> = (0..MAX_CLIENTS as u32).map(|id| PlayerSnapshot { id: id, ..moving_player() }).collect();
+        let projectiles: Vec<_> = (0..MAX_SNAPSHOT_PROJECTILES * 2).map(|n| Projectile {
+            kind: WeaponKind::Rocket,
+            owner: 0,
+            pos: na::Pnt3::new(n as f32, 0.0, 0.0),
+            vel: na::Vec3::new(0.0, 0.0, 900.0),
+            spawntime: n as f32,
+        }).collect();
+        let movers: Vec<_> = (0..32).map(|n| MoverSnapshot {
+            entity: n,
+            offset: na::Vec3::new(0.0, -64.0, 0.0),
+            state: MoverState::Moving { from: 0, to: 1, progress: 0.5 },
+        }).collect();
+        let snap = Snapshot {
+            tick: 100,
+            time: 0.5,
+            basetick: 0,
+            precision: Precision::Full,
+            ack: 99,
+            you: 0,
+            players: players.clone(),
+            projectiles: projectiles.clone(),
+            movers: movers,
+        };
+        let history = History::new();
+        let buf = ServerMessage::Snapshot(snap).encode(&history);
+        assert!(buf.len() <= MAX_PACKET);
+
+        match ServerMessage::decode(&buf, &history).unwrap() {
+            ServerMessage::Snapshot(decoded) => {
+                assert_eq!(decoded.players, players);
+                // Whatever projectiles made it are the newest ones.
+                let sent = decoded.projectiles.len();
+                assert_eq!(&decoded.projectiles[..], &projectiles[projectiles.len() - sent..]);
+            },
+            _ => panic!("not a snapshot")
+        }
+    }
+
+    #[test]
+    fn oversized_packets_are_refused() {
+        let history = History::new();
+        assert!(ServerMessage::decode(&vec![0; MAX_PACKET + 1], &history).is_err());
+    }
+
+    /// `floor_map` with a train going back and forth on it.
+    fn train_map() -> (map::Map, u32) {
+        let mut map = floor_map();
+        let train = add_mover(&mut map, na::Pnt3::new(-200.0, 8.0, 0.0), na::Vec3::new(64.0, 8.0, 64.0),
+                              map::mover::Mover::new(map::mover::MoverKind::Train,
+                                                     vec![na::zero(), na::Vec3::new(128.0, 0.0, 0.0)],
+                                                     32.0, 0.5));
+        (map, train)
+    }
+
+    #[test]
+    fn prediction_ends_up_where_the_server_does() {
+        let (map, train) = train_map();
+        let mut server = Game::new(map);
+        let mut client = Game::new(train_map().0);
+        server.add_player();
+        client.add_player();
+        let mut prediction = Prediction::new();
+
+        // Snapshots take ten ticks to arrive.
+        let mut inflight = VecDeque::new();
+        for i in 0..400u32 {
+            let mi = input(na::Vec3::new(150.0, 0.0, -220.0), i as f32 * 0.013, i % 90 < 10);
+            let sequence = i + 1;
+            server.tick(&[mi.clone()]);
+            inflight.push_back(snapshot(&server, sequence));
+            prediction.predict(&mut client, 0, sequence, mi);
+
+            if i == 200 {
+                // Knock the client off course, so it has something to put right.
+                client.players[0].pos.x += 20.0;
+                client.players[0].vel.y -= 300.0;
+                client.players[0].health = 10.0;
+                if let Some(ref mut mover) = client.map.entities[train as usize].mover {
+                    mover.offset.x += 16.0;
+                }
+            }
+            if inflight.len() > 10 {
+                let snap = inflight.pop_front().unwrap();
+                assert_eq!(prediction.reconcile(&mut client, &snap), Some(0));
+            }
+        }
+
+        assert_eq!(client.tickcount, server.tickcount);
+        assert_eq!(client.time, server.time);
+        assert_eq!(PlayerSnapshot::new(&client.players[0]), PlayerSnapshot::new(&server.players[0]));
+        assert_eq!(MoverSnapshot::from_map(&client.map), MoverSnapshot::from_map(&server.map));
+    }
+}
@@ -0,0 +1,633 @@
+use na;
+use byteorder::{self, LittleEndian, ReadBytesExt, WriteBytesExt};
+use std::collections::VecDeque;
+use std::f32::consts::PI_2;
+use std::io::Cursor;
+use player::{Hull, Player, PlayerFlags, PlayerState};
+use super::{read_vec, write_vec};
+
+/// How many old snapshots to keep around to delta against.
+const HISTORY_LENGTH: usize = 64;
+
+/// How exactly player state is sent.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Precision {
+    /// Every value as a full float.
+    Full,
+    /// Positions and speeds to an eighth of a unit, angles to 16 bits, times to the millisecond.
+    Quantized,
+}
+
+/// What other machines need to know about a player: everything movement reads,
+/// so a client can pick up from it and end up where the server does.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct PlayerSnapshot {
+    /// Which player this is. Always sent, since it's what deltas are matched up by.
+    pub id: u32,
+    pub pos: na::Pnt3<f32>,
+    pub vel: na::Vec3<f32>,
+    pub eyeang: na::Vec3<f32>,
+    pub flags: PlayerFlags,
+    pub landtime: f32,
+    pub holdjumptime: f32,
+    pub wallruntime: f32,
+    pub nextfire: f32,
+    pub hook: Option<na::Pnt3<f32>>,
+    pub hooklength: f32,
+    pub halfextents: na::Vec3<f32>,
+    pub eyeheight: f32,
+    pub groundentity: Option<u32>,
+    pub groundnormal: Option<na::Vec3<f32>>,
+    pub groundflags: i32,
+    pub wallnormal: Option<na::Vec3<f32>>,
+    pub lastwalljump: Option<na::Vec3<f32>>,
+    pub health: f32,
+    pub armor: f32,
+    pub fallspeed: f32,
+    pub steptime: f32,
+    pub stepchange: f32,
+}
+
+// Which fields a delta-encoded player carries.
+const FIELD_POS: u16 = 0b00_00_00_01;
+const FIELD_VEL: u16 = 0b00_00_00_10;
+const FIELD_EYEANG: u16 = 0b00_00_01_00;
+const FIELD_FLAGS: u16 = 0b00_00_10_00;
+const FIELD_LANDTIME: u16 = 0b00_01_00_00;
+const FIELD_HOLDJUMPTIME: u16 = 0b00_10_00_00;
+const FIELD_WALLRUNTIME: u16 = 0b01_00_00_00;
+const FIELD_NEXTFIRE: u16 = 0b10_00_00_00;
+const FIELD_HOOK: u16 = 0b01_00_00_00_00;
+const FIELD_HOOKLENGTH: u16 = 0b10_00_00_00_00;
+/// `halfextents` and `eyeheight`.
+const FIELD_HULL: u16 = 0b01_00_00_00_00_00;
+/// `groundentity`, `groundnormal` and `groundflags`.
+const FIELD_GROUND: u16 = 0b10_00_00_00_00_00;
+/// `wallnormal` and `lastwalljump`.
+const FIELD_WALL: u16 = 0b01_00_00_00_00_00_00;
+/// `health` and `armor`.
+const FIELD_HEALTH: u16 = 0b10_00_00_00_00_00_00;
+const FIELD_FALLSPEED: u16 = 0b01_00_00_00_00_00_00_00;
+/// `steptime` and `stepchange`.
+const FIELD_STEP: u16 = 0b10_00_00_00_00_00_00_00;
+
+impl PlayerSnapshot {
+    pub fn new(pl: &Player) -> PlayerSnapshot {
+        PlayerSnapshot {
+            id: pl.id,
+            pos: pl.pos,
+            vel: pl.vel,
+            eyeang: pl.eyeang,
+            flags: pl.flags,
+            landtime: pl.landtime,
+            holdjumptime: pl.holdjumptime,
+            wallruntime: pl.wallruntime,
+            nextfire: pl.nextfire,
+            hook: pl.hook,
+            hooklength: pl.hooklength,
+            halfextents: pl.halfextents,
+            eyeheight: pl.eyeheight,
+            groundentity: pl.groundentity,
+            groundnormal: pl.groundnormal,
+            groundflags: pl.groundflags,
+            wallnormal: pl.wallnormal,
+            lastwalljump: pl.lastwalljump,
+            health: pl.health,
+            armor: pl.armor,
+            fallspeed: pl.fallspeed,
+            steptime: pl.steptime,
+            stepchange: pl.stepchange,
+        }
+    }
+
+    /// What a player is delta-encoded against when there's nothing better.
+    pub fn baseline() -> PlayerSnapshot {
+        let hull = Hull::standing();
+        PlayerSnapshot {
+            id: 0,
+            pos: na::Pnt3::new(0.0, 0.0, 0.0),
+            vel: na::zero(),
+            eyeang: na::zero(),
+            flags: PlayerFlags::empty(),
+            landtime: 0.0,
+            holdjumptime: 0.0,
+            wallruntime: 0.0,
+            nextfire: 0.0,
+            hook: None,
+            hooklength: 0.0,
+            halfextents: hull.halfextents,
+            eyeheight: hull.eyeheight,
+            groundentity: None,
+            groundnormal: None,
+            groundflags: 0,
+            wallnormal: None,
+            lastwalljump: None,
+            health: 100.0,
+            armor: 0.0,
+            fallspeed: 0.0,
+            steptime: 0.0,
+            stepchange: 0.0,
+        }
+    }
+
+    pub fn apply(&self, pl: &mut Player) {
+        pl.id = self.id;
+        pl.pos = self.pos;
+        pl.vel = self.vel;
+        pl.eyeang = self.eyeang;
+        pl.flags = self.flags;
+        pl.landtime = self.landtime;
+        pl.holdjumptime = self.holdjumptime;
+        pl.wallruntime = self.wallruntime;
+        pl.nextfire = self.nextfire;
+        pl.hook = self.hook;
+        pl.hooklength = self.hooklength;
+        pl.halfextents = self.halfextents;
+        pl.eyeheight = self.eyeheight;
+        pl.groundentity = self.groundentity;
+        pl.groundnormal = self.groundnormal;
+        pl.groundflags = self.groundflags;
+        pl.wallnormal = self.wallnormal;
+        pl.lastwalljump = self.lastwalljump;
+        pl.health = self.health;
+        pl.armor = self.armor;
+        pl.fallspeed = self.fallspeed;
+        pl.steptime = self.steptime;
+        pl.stepchange = self.stepchange;
+    }
+
+    /// Just what's needed to draw the player.
+    pub fn state(&self) -> PlayerState {
+        PlayerState {
+            pos: self.pos,
+            vel: self.vel,
+            eyeang: self.eyeang,
+        }
+    }
+
+    /// Whether two states are close enough that nobody would notice the difference.
+    pub fn matches(&self, other: &PlayerSnapshot) -> bool {
+        na::norm(&(self.pos.to_vec() - other.pos.to_vec())) < 0.1 &&
+            na::norm(&(self.vel - other.vel)) < 1.0 &&
+            self.flags == other.flags &&
+            self.halfextents == other.halfextents
+    }
+
+    /// This state as the other end will see it after sending it at `precision`.
+    /// Hulls and normals always go as full floats.
+    pub fn quantized(&self, precision: Precision) -> PlayerSnapshot {
+        match precision {
+            Precision::Full => *self,
+            Precision::Quantized => PlayerSnapshot {
+                id: self.id,
+                pos: quantize_pos(&self.pos),
+                vel: map_vec(&self.vel, |n| from_eighths(to_eighths_short(n) as i32)),
+                eyeang: map_vec(&self.eyeang, |a| from_short_angle(to_short_angle(a))),
+                flags: self.flags,
+                landtime: from_millis(to_millis(self.landtime)),
+                holdjumptime: from_millis(to_millis(self.holdjumptime)),
+                wallruntime: from_millis(to_millis(self.wallruntime)),
+                nextfire: from_millis(to_millis(self.nextfire)),
+                hook: self.hook.map(|hook| quantize_pos(&hook)),
+                hooklength: from_eighths(to_eighths(self.hooklength)),
+                health: from_eighths(to_eighths(self.health)),
+                armor: from_eighths(to_eighths(self.armor)),
+                fallspeed: from_eighths(to_eighths(self.fallspeed)),
+                steptime: from_millis(to_millis(self.steptime)),
+                stepchange: from_eighths(to_eighths(self.stepchange)),
+                .. *self
+            }
+        }
+    }
+}
+
+fn map_vec<F: Fn(f32) -> f32>(v: &na::Vec3<f32>, f: F) -> na::Vec3<f32> {
+    na::Vec3::new(f(v.x), f(v.y), f(v.z))
+}
+
+fn quantize_pos(p: &na::Pnt3<f32>) -> na::Pnt3<f32> {
+    map_vec(&p.to_vec(), |n| from_eighths(to_eighths(n))).to_pnt()
+}
+
+fn to_eighths(n: f32) -> i32 {
+    (n * 8.0).round() as i32
+}
+fn to_eighths_short(n: f32) -> i16 {
+    na::clamp((n * 8.0).round(), -32767.0, 32767.0) as i16
+}
+fn from_eighths(n: i32) -> f32 {
+    n as f32 / 8.0
+}
+fn to_short_angle(a: f32) -> i16 {
+    ((a / PI_2 * 65536.0).round() as i64 & 0xffff) as u16 as i16
+}
+fn from_short_angle(a: i16) -> f32 {
+    a as f32 * PI_2 / 65536.0
+}
+fn to_millis(t: f32) -> i32 {
+    (t * 1000.0).round() as i32
+}
+fn from_millis(t: i32) -> f32 {
+    t as f32 / 1000.0
+}
+
+fn write_time(buf: &mut Vec<u8>, t: f32, precision: Precision) {
+    match precision {
+        Precision::Full => buf.write_f32::<LittleEndian>(t).unwrap(),
+        Precision::Quantized => buf.write_i32::<LittleEndian>(to_millis(t)).unwrap(),
+    }
+}
+fn read_time(cursor: &mut Cursor<&[u8]>, precision: Precision) -> byteorder::Result<f32> {
+    match precision {
+        Precision::Full => cursor.read_f32::<LittleEndian>(),
+        Precision::Quantized => Ok(from_millis(try!(cursor.read_i32::<LittleEndian>()))),
+    }
+}
+
+fn write_pos(buf: &mut Vec<u8>, p: &na::Pnt3<f32>, precision: Precision) {
+    match precision {
+        Precision::Full => write_vec(buf, &p.to_vec()),
+        Precision::Quantized => {
+            buf.write_i32::<LittleEndian>(to_eighths(p.x)).unwrap();
+            buf.write_i32::<LittleEndian>(to_eighths(p.y)).unwrap();
+            buf.write_i32::<LittleEndian>(to_eighths(p.z)).unwrap();
+        }
+    }
+}
+fn read_pos(cursor: &mut Cursor<&[u8]>, precision: Precision) -> byteorder::Result<na::Pnt3<f32>> {
+    match precision {
+        Precision::Full => Ok(try!(read_vec(cursor)).to_pnt()),
+        Precision::Quantized => {
+            let x = try!(cursor.read_i32::<LittleEndian>());
+            let y = try!(cursor.read_i32::<LittleEndian>());
+            let z = try!(cursor.read_i32::<LittleEndian>());
+            Ok(na::Pnt3::new(from_eighths(x), from_eighths(y), from_eighths(z)))
+        }
+    }
+}
+
+fn write_length(buf: &mut Vec<u8>, n: f32, precision: Precision) {
+    match precision {
+        Precision::Full => buf.write_f32::<LittleEndian>(n).unwrap(),
+        Precision::Quantized => buf.write_i32::<LittleEndian>(to_eighths(n)).unwrap(),
+    }
+}
+fn read_length(cursor: &mut Cursor<&[u8]>, precision: Precision) -> byteorder::Result<f32> {
+    match precision {
+        Precision::Full => cursor.read_f32::<LittleEndian>(),
+        Precision::Quantized => Ok(from_eighths(try!(cursor.read_i32::<LittleEndian>()))),
+    }
+}
+
+fn write_normal(buf: &mut Vec<u8>, n: &Option<na::Vec3<f32>>) {
+    match *n {
+        Some(ref n) => {
+            buf.write_u8(1).unwrap();
+            write_vec(buf, n);
+        },
+        None => buf.write_u8(0).unwrap(),
+    }
+}
+fn read_normal(cursor: &mut Cursor<&[u8]>) -> byteorder::Result<Option<na::Vec3<f32>>> {
+    match try!(cursor.read_u8()) {
+        0 => Ok(None),
+        _ => Ok(Some(try!(read_vec(cursor))))
+    }
+}
+
+/// Writes only the fields of `pl` that differ from `base`, which the reader must also have.
+pub fn write_delta(buf: &mut Vec<u8>, base: &PlayerSnapshot, pl: &PlayerSnapshot, precision: Precision) {
+    let pl = pl.quantized(precision);
+    let mut fields = 0;
+    if pl.pos != base.pos { fields |= FIELD_POS; }
+    if pl.vel != base.vel { fields |= FIELD_VEL; }
+    if pl.eyeang != base.eyeang { fields |= FIELD_EYEANG; }
+    if pl.flags != base.flags { fields |= FIELD_FLAGS; }
+    if pl.landtime != base.landtime { fields |= FIELD_LANDTIME; }
+    if pl.holdjumptime != base.holdjumptime { fields |= FIELD_HOLDJUMPTIME; }
+    if pl.wallruntime != base.wallruntime { fields |= FIELD_WALLRUNTIME; }
+    if pl.nextfire != base.nextfire { fields |= FIELD_NEXTFIRE; }
+    if pl.hook != base.hook { fields |= FIELD_HOOK; }
+    if pl.hooklength != base.hooklength { fields |= FIELD_HOOKLENGTH; }
+    if pl.halfextents != base.halfextents || pl.eyeheight != base.eyeheight { fields |= FIELD_HULL; }
+    if pl.groundentity != base.groundentity || pl.groundnormal != base.groundnormal ||
+        pl.groundflags != base.groundflags { fields |= FIELD_GROUND; }
+    if pl.wallnormal != base.wallnormal || pl.lastwalljump != base.lastwalljump { fields |= FIELD_WALL; }
+    if pl.health != base.health || pl.armor != base.armor { fields |= FIELD_HEALTH; }
+    if pl.fallspeed != base.fallspeed { fields |= FIELD_FALLSPEED; }
+    if pl.steptime != base.steptime || pl.stepchange != base.stepchange { fields |= FIELD_STEP; }
+    buf.write_u16::<LittleEndian>(fields).unwrap();
+
+    if fields & FIELD_POS != 0 { write_pos(buf, &pl.pos, precision); }
+    if fields & FIELD_VEL != 0 {
+        match precision {
+            Precision::Full => write_vec(buf, &pl.vel),
+            Precision::Quantized => {
+                buf.write_i16::<LittleEndian>(to_eighths_short(pl.vel.x)).unwrap();
+                buf.write_i16::<LittleEndian>(to_eighths_short(pl.vel.y)).unwrap();
+                buf.write_i16::<LittleEndian>(to_eighths_short(pl.vel.z)).unwrap();
+            }
+        }
+    }
+    if fields & FIELD_EYEANG != 0 {
+        match precision {
+            Precision::Full => write_vec(buf, &pl.eyeang),
+            Precision::Quantized => {
+                buf.write_i16::<LittleEndian>(to_short_angle(pl.eyeang.x)).unwrap();
+                buf.write_i16::<LittleEndian>(to_short_angle(pl.eyeang.y)).unwrap();
+                buf.write_i16::<LittleEndian>(to_short_angle(pl.eyeang.z)).unwrap();
+            }
+        }
+    }
+    if fields & FIELD_FLAGS != 0 {
+        buf.write_u16::<LittleEndian>(pl.flags.bits() as u16).unwrap();
+    }
+    if fields & FIELD_LANDTIME != 0 { write_time(buf, pl.landtime, precision); }
+    if fields & FIELD_HOLDJUMPTIME != 0 { write_time(buf, pl.holdjumptime, precision); }
+    if fields & FIELD_WALLRUNTIME != 0 { write_time(buf, pl.wallruntime, precision); }
+    if fields & FIELD_NEXTFIRE != 0 { write_time(buf, pl.nextfire, precision); }
+    if fields & FIELD_HOOK != 0 {
+        match pl.hook {
+            Some(ref hook) => {
+                buf.write_u8(1).unwrap();
+                write_pos(buf, hook, precision);
+            },
+            None => buf.write_u8(0).unwrap(),
+        }
+    }
+    if fields & FIELD_HOOKLENGTH != 0 { write_length(buf, pl.hooklength, precision); }
+    if fields & FIELD_HULL != 0 {
+        write_vec(buf, &pl.halfextents);
+        buf.write_f32::<LittleEndian>(pl.eyeheight).unwrap();
+    }
+    if fields & FIELD_GROUND != 0 {
+        match pl.groundentity {
+            Some(entity) => {
+                buf.write_u8(1).unwrap();
+                buf.write_u32::<LittleEndian>(entity).unwrap();
+            },
+            None => buf.write_u8(0).unwrap(),
+        }
+        write_normal(buf, &pl.groundnormal);
+        buf.write_i32::<LittleEndian>(pl.groundflags).unwrap();
+    }
+    if fields & FIELD_WALL != 0 {
+        write_normal(buf, &pl.wallnormal);
+        write_normal(buf, &pl.lastwalljump);
+    }
+    if fields & FIELD_HEALTH != 0 {
+        write_length(buf, pl.health, precision);
+        write_length(buf, pl.armor, precision);
+    }
+    if fields & FIELD_FALLSPEED != 0 { write_length(buf, pl.fallspeed, precision); }
+    if fields & FIELD_STEP != 0 {
+        write_time(buf, pl.steptime, precision);
+        write_length(buf, pl.stepchange, precision);
+    }
+}
+
+pub fn read_delta(cursor: &mut Cursor<&[u8]>, base: &PlayerSnapshot, precision: Precision) -> byteorder::Result<PlayerSnapshot> {
+    let mut pl = *base;
+    let fields = try!(cursor.read_u16::<LittleEndian>());
+
+    if fields & FIELD_POS != 0 { pl.pos = try!(read_pos(cursor, precision)); }
+    if fields & FIELD_VEL != 0 {
+        pl.vel = match precision {
+            Precision::Full => try!(read_vec(cursor)),
+            Precision::Quantized => {
+                let x = try!(cursor.read_i16::<LittleEndian>());
+                let y = try!(cursor.read_i16::<LittleEndian>());
+                let z = try!(cursor.read_i16::<LittleEndian>());
+                na::Vec3::new(from_eighths(x as i32), from_eighths(y as i32), from_eighths(z as i32))
+            }
+        };
+    }
+    if fields & FIELD_EYEANG != 0 {
+        pl.eyeang = match precision {
+            Precision::Full => try!(read_vec(cursor)),
+            Precision::Quantized => {
+                let x = try!(cursor.read_i16::<LittleEndian>());
+                let y = try!(cursor.read_i16::<LittleEndian>());
+                let z = try!(cursor.read_i16::<LittleEndian>());
+                na::Vec3::new(from_short_angle(x), from_short_angle(y), from_short_angle(z))
+            }
+        };
+    }
+    if fields & FIELD_FLAGS != 0 {
+        pl.flags = PlayerFlags::from_bits_truncate(try!(cursor.read_u16::<LittleEndian>()) as u32);
+    }
+    if fields & FIELD_LANDTIME != 0 { pl.landtime = try!(read_time(cursor, precision)); }
+    if fields & FIELD_HOLDJUMPTIME != 0 { pl.holdjumptime = try!(read_time(cursor, precision)); }
+    if fields & FIELD_WALLRUNTIME != 0 { pl.wallruntime = try!(read_time(cursor, precision)); }
+    if fields & FIELD_NEXTFIRE != 0 { pl.nextfire = try!(read_time(cursor, precision)); }
+    if fields & FIELD_HOOK != 0 {
+        pl.hook = match try!(cursor.read_u8()) {
+            0 => None,
+            _ => Some(try!(read_pos(cursor, precision)))
+        };
+    }
+    if fields & FIELD_HOOKLENGTH != 0 { pl.hooklength = try!(read_length(cursor, precision)); }
+    if fields & FIELD_HULL != 0 {
+        pl.halfextents = try!(read_vec(cursor));
+        pl.eyeheight = try!(cursor.read_f32::<LittleEndian>());
+    }
+    if fields & FIELD_GROUND != 0 {
+        pl.groundentity = match try!(cursor.read_u8()) {
+            0 => None,
+            _ => Some(try!(cursor.read_u32::<LittleEndian>()))
+        };
+        pl.groundnormal = try!(read_normal(cursor));
+        pl.groundflags = try!(cursor.read_i32::<LittleEndian>());
+    }
+    if fields & FIELD_WALL != 0 {
+        pl.wallnormal = try!(read_normal(cursor));
+        pl.lastwalljump = try!(read_normal(cursor));
+    }
+    if fields & FIELD_HEALTH != 0 {
+        pl.health = try!(read_length(cursor, precision));
+        pl.armor = try!(read_length(cursor, precision));
+    }
+    if fields & FIELD_FALLSPEED != 0 { pl.fallspeed = try!(read_length(cursor, precision)); }
+    if fields & FIELD_STEP != 0 {
+        pl.steptime = try!(read_time(cursor, precision));
+        pl.stepchange = try!(read_length(cursor, precision));
+    }
+    Ok(pl)
+}
+
+/// The player in `base` with the same id, or the baseline if they weren't there.
+fn base_for(base: &[PlayerSnapshot], id: u32) -> PlayerSnapshot {
+    base.iter()
+        .find(|pl| pl.id == id)
+        .map(|pl| *pl)
+        .unwrap_or(PlayerSnapshot { id: id, ..PlayerSnapshot::baseline() })
+}
+
+/// Players are matched up with `base` by id, so it doesn't matter who's joined or left since.
+/// There can be at most 255 of them.
+pub fn write_players(buf: &mut Vec<u8>, base: &[PlayerSnapshot], players: &[PlayerSnapshot], precision: Precision) {
+    assert!(players.len() <= 255);
+    buf.write_u8(players.len() as u8).unwrap();
+    for pl in players {
+        buf.write_u32::<LittleEndian>(pl.id).unwrap();
+        write_delta(buf, &base_for(base, pl.id), pl, precision);
+    }
+}
+
+pub fn read_players(cursor: &mut Cursor<&[u8]>, base: &[PlayerSnapshot], precision: Precision) -> byteorder::Result<Vec<PlayerSnapshot>> {
+    let n_players = try!(cursor.read_u8());
+    let mut players = vec![];
+    for _ in 0..n_players {
+        let id = try!(cursor.read_u32::<LittleEndian>());
+        players.push(try!(read_delta(cursor, &base_for(base, id), precision)));
+    }
+    Ok(players)
+}
+
+/// Snapshots recently sent or received, by tick, as the client sees them.
+pub struct History {
+    snapshots: VecDeque<(u64, Vec<PlayerSnapshot>)>,
+}
+impl History {
+    pub fn new() -> History {
+        History {
+            snapshots: VecDeque::new(),
+        }
+    }
+
+    pub fn push(&mut self, tick: u64, players: Vec<PlayerSnapshot>) {
+        if self.snapshots.len() >= HISTORY_LENGTH {
+            self.snapshots.pop_front();
+        }
+        self.snapshots.push_back((tick, players));
+    }
+
+    pub fn get(&self, tick: u64) -> Option<&[PlayerSnapshot]> {
+        self.snapshots.iter()
+            .find(|&&(t, _)| t == tick)
+            .map(|&(_, ref players)| &players[..])
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use na;
+    use std::io::Cursor;
+    use player::{PLAYER_ONGROUND, PLAYER_JUMPED};
+    use super::{
+        PlayerSnapshot,
+        Precision,
+        write_delta,
+        read_delta,
+        write_players,
+        read_players,
+    };
+
+    /// A player with every field set to something other than the baseline.
+    pub fn moving_player() -> PlayerSnapshot {
+        PlayerSnapshot {
+            id: 3,
+            pos: na::Pnt3::new(123.456, -78.9, 1024.125),
+            vel: na::Vec3::new(-310.3, 270.0, 0.02),
+            eyeang: na::Vec3::new(-0.4, 5.2, 0.0),
+            flags: PLAYER_ONGROUND | PLAYER_JUMPED,
+            landtime: 12.3456,
+            holdjumptime: 11.9,
+            wallruntime: 0.0,
+            nextfire: 13.0,
+            hook: Some(na::Pnt3::new(100.0, -300.25, 990.0)),
+            hooklength: 75.3,
+            halfextents: na::Vec3::new(8.0, 7.0, 8.0),
+            eyeheight: 4.2,
+            groundentity: Some(2),
+            groundnormal: Some(na::Vec3::new(0.0, -0.8, 0.6)),
+            groundflags: 0x20,
+            wallnormal: Some(na::Vec3::new(1.0, 0.0, 0.0)),
+            lastwalljump: Some(na::Vec3::new(0.0, 0.0, -1.0)),
+            health: 87.5,
+            armor: 12.3,
+            fallspeed: 412.7,
+            steptime: 12.1,
+            stepchange: -6.3,
+        }
+    }
+
+    fn round_trip(base: &PlayerSnapshot, pl: &PlayerSnapshot, precision: Precision) -> (PlayerSnapshot, usize) {
+        let mut buf = vec![];
+        write_delta(&mut buf, base, pl, precision);
+        let decoded = read_delta(&mut Cursor::new(&buf[..]), base, precision).unwrap();
+        (decoded, buf.len())
+    }
+
+    #[test]
+    fn full_precision_is_exact() {
+        let pl = moving_player();
+        let (decoded, _) = round_trip(&PlayerSnapshot::baseline(), &pl, Precision::Full);
+        assert_eq!(decoded, pl);
+    }
+
+    #[test]
+    fn quantized_matches_quantized() {
+        let pl = moving_player();
+        let (decoded, _) = round_trip(&PlayerSnapshot::baseline(), &pl, Precision::Quantized);
+        assert_eq!(decoded, pl.quantized(Precision::Quantized));
+        assert!(na::norm(&(decoded.pos.to_vec() - pl.pos.to_vec())) < 0.125);
+        assert!(na::approx_eq_eps(&decoded.landtime, &pl.landtime, &0.001));
+    }
+
+    #[test]
+    fn unchanged_costs_two_bytes() {
+        for &precision in &[Precision::Full, Precision::Quantized] {
+            let pl = moving_player().quantized(precision);
+            let (decoded, len) = round_trip(&pl, &pl, precision);
+            assert_eq!(decoded, pl);
+            assert_eq!(len, 2);
+        }
+    }
+
+    #[test]
+    fn delta_only_sends_changes() {
+        for &precision in &[Precision::Full, Precision::Quantized] {
+            let base = moving_player().quantized(precision);
+            let mut pl = base;
+            pl.pos.x += 10.0;
+            pl.vel.y = -200.0;
+            let (decoded, len) = round_trip(&base, &pl, precision);
+            let (_, fulllen) = round_trip(&PlayerSnapshot::baseline(), &pl, precision);
+            assert_eq!(decoded, pl.quantized(precision));
+            assert!(len < fulllen);
+        }
+    }
+
+    #[test]
+    fn players_joining_and_leaving() {
+        let precision = Precision::Quantized;
+        let base = vec![moving_player().quantized(precision)];
+        let mut second = moving_player();
+        second.id = 4;
+        second.pos.y = 500.0;
+        for players in &[vec![], vec![moving_player()], vec![moving_player(), second]] {
+            let mut buf = vec![];
+            write_players(&mut buf, &base, players, precision);
+            let decoded = read_players(&mut Cursor::new(&buf[..]), &base, precision).unwrap();
+            let expected: Vec<_> = players.iter().map(|pl| pl.quantized(precision)).collect();
+            assert_eq!(decoded, expected);
+        }
+    }
+
+    #[test]
+    fn players_matched_by_id() {
+        let precision = Precision::Full;
+        let mut first = moving_player();
+        first.id = 1;
+        let second = moving_player();
+        let base = vec![first, second];
+        // The first player leaves, so the second moves up a slot but should still delta against itself.
+        let mut buf = vec![];
+        write_players(&mut buf, &base, &[second], precision);
+        let decoded = read_players(&mut Cursor::new(&buf[..]), &base, precision).unwrap();
+        assert_eq!(decoded, vec![second]);
+        // A byte for the count, four for the id and two saying nothing changed.
+        assert_eq!(buf.len(), 7);
+    }
+}
@@ -1,23 +1,84 @@
 use na;
+use settings::HealthSettings;
+use std::f32::consts::{PI, PI_2, FRAC_PI_2, FRAC_2_PI};
 
 pub mod movement;
 
-// remove this later
-pub const PLAYER_HALFEXTENTS: na::Vec3<f32> = na::Vec3 { x: 8.0, y: 12.0, z: 8.0 };
+/// The size of a player's collision box, and where their eyes sit in it.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Hull {
+    pub halfextents: na::Vec3<f32>,
+    /// How far above the center of the box the eyes are.
+    pub eyeheight: f32,
+}
+impl Hull {
+    pub fn standing() -> Hull {
+        Hull {
+            halfextents: na::Vec3::new(8.0, 12.0, 8.0),
+            eyeheight: 7.2,
+        }
+    }
+
+    /// The same width as this hull but shorter, for crouching.
+    /// Staying as wide means ducking can never push the box into a wall.
+    pub fn crouched(&self) -> Hull {
+        let scale = 7.0 / 12.0;
+        Hull {
+            halfextents: na::Vec3::new(self.halfextents.x, self.halfextents.y * scale, self.halfextents.z),
+            eyeheight: self.eyeheight * scale,
+        }
+    }
+
+    /// A smaller hull that fits through tighter gaps.
+    pub fn runner() -> Hull {
+        Hull {
+            halfextents: na::Vec3::new(6.0, 8.0, 6.0),
+            eyeheight: 5.0,
+        }
+    }
+
+    pub fn named(name: &str) -> Option<Hull> {
+        match name {
+            "standing" => Some(Hull::standing()),
+            "crouched" => Some(Hull::standing().crouched()),
+            "runner" => Some(Hull::runner()),
+            _ => None
+        }
+    }
+}
+
+/// How long the view takes to catch up after stepping up.
+pub const STEP_TIME: f32 = 0.2;
 
 bitflags! {
+    #[derive(Debug)]
     flags PlayerFlags: u32 {
         const PLAYER_ONGROUND = 0b00_00_00_01,
         const PLAYER_HOLDING_JUMP = 0b00_00_00_10,
         const PLAYER_CAN_STEP = 0b00_00_01_00,
         const PLAYER_MUST_DIE = 0b00_00_10_00,
+        const PLAYER_CROUCHED = 0b00_01_00_00,
+        /// Landed while crouched; keeps momentum for `crouchslidetime`.
+        const PLAYER_SLIDING = 0b00_10_00_00,
+        const PLAYER_WALLRUNNING = 0b01_00_00_00,
+        /// Ran out of wall-run time; can't start another until landing or wall-jumping.
+        const PLAYER_WALLRUN_SPENT = 0b10_00_00_00,
+        /// Left the ground by jumping, so landing opens the double-jump window.
+        const PLAYER_JUMPED = 0b01_00_00_00_00,
+        const PLAYER_HOLDING_HOOK = 0b10_00_00_00_00,
+        const PLAYER_ONLADDER = 0b01_00_00_00_00_00,
+        /// Standing on a surface too steep to walk on, so sliding down it.
+        const PLAYER_ONRAMP = 0b10_00_00_00_00_00,
     }
 }
 
 pub struct Player {
+    /// Stays the same for as long as the player is in the game, unlike their index.
+    pub id: u32,
     pub pos: na::Pnt3<f32>,
     pub flags: PlayerFlags,
     pub vel: na::Vec3<f32>,
+    /// How far above `pos` the eyes are.
     pub eyeheight: f32,
     pub halfextents: na::Vec3<f32>,
     pub eyeang: na::Vec3<f32>,
@@ -25,4 +86,177 @@ pub struct Player {
     pub viewpunch_vel: na::Vec3<f32>,
     pub landtime: f32,
     pub holdjumptime: f32,
+    /// The entity the player is standing on, if any. Movers carry their riders.
+    pub groundentity: Option<u32>,
+    /// Normal of the surface under the player, walkable or not.
+    pub groundnormal: Option<na::Vec3<f32>>,
+    /// Surface flags of what's under the player.
+    pub groundflags: i32,
+    /// Normal of the wall the player is up against, if any.
+    pub wallnormal: Option<na::Vec3<f32>>,
+    /// Normal of the wall last jumped off. It can't be jumped off again until landing.
+    pub lastwalljump: Option<na::Vec3<f32>>,
+    /// When the current wall-run started.
+    pub wallruntime: f32,
+    /// Earliest time the player can fire again.
+    pub nextfire: f32,
+    /// Where the grappling hook is attached, if it is.
+    pub hook: Option<na::Pnt3<f32>>,
+    /// Current length of the hook's rope.
+    pub hooklength: f32,
+    pub health: f32,
+    pub armor: f32,
+    /// Downward speed going into the last move, for working out landing impacts.
+    pub fallspeed: f32,
+    /// What happened during the last move. Cleared at the start of each move.
+    pub events: Vec<movement::MoveEvent>,
+    /// When the player last stepped up.
+    pub steptime: f32,
+    /// How far the view still had to catch up when the player last stepped up.
+    pub stepchange: f32,
+}
+impl Player {
+    /// A player at the origin, using `hull`.
+    pub fn new(hull: &Hull) -> Player {
+        Player {
+            id: 0,
+            pos: na::Pnt3::new(0.0, 0.0, 0.0),
+            eyeheight: hull.eyeheight,
+            eyeang: na::zero(),
+            viewpunch: na::zero(),
+            viewpunch_vel: na::zero(),
+            halfextents: hull.halfextents,
+            vel: na::zero(),
+            flags: PlayerFlags::empty(),
+            landtime: 0.0,
+            holdjumptime: 0.0,
+            groundentity: None,
+            groundnormal: None,
+            groundflags: 0,
+            wallnormal: None,
+            lastwalljump: None,
+            wallruntime: 0.0,
+            nextfire: 0.0,
+            hook: None,
+            hooklength: 0.0,
+            health: 100.0,
+            armor: 0.0,
+            fallspeed: 0.0,
+            events: vec![],
+            steptime: 0.0,
+            stepchange: 0.0,
+        }
+    }
+
+    pub fn set_hull(&mut self, hull: &Hull) {
+        self.halfextents = hull.halfextents;
+        self.eyeheight = hull.eyeheight;
+    }
+
+    /// How far below its real height the view should be, to ease up steps.
+    pub fn step_offset(&self, time: f32) -> f32 {
+        let delta = time - self.steptime;
+        if delta < STEP_TIME {
+            self.stepchange * (STEP_TIME - delta) / STEP_TIME
+        } else {
+            0.0
+        }
+    }
+
+    /// Armor soaks up two thirds of the damage while it lasts.
+    pub fn damage(&mut self, amount: f32, settings: &HealthSettings) {
+        if amount <= 0.0 {
+            return;
+        }
+        if settings.hardcore {
+            self.health = 0.0;
+        } else {
+            let saved = na::clamp(amount * 2.0 / 3.0, 0.0, self.armor);
+            self.armor -= saved;
+            self.health -= amount - saved;
+        }
+
+        if self.health <= 0.0 {
+            self.flags.insert(PLAYER_MUST_DIE);
+        }
+    }
+
+    /// Where the player's eyes are.
+    pub fn eyepos(&self) -> na::Pnt3<f32> {
+        self.pos + self.eye_offset()
+    }
+
+    /// Where the player's eyes are, relative to `pos`.
+    pub fn eye_offset(&self) -> na::Vec3<f32> {
+        na::Vec3::new(0.0, -self.eyeheight, 0.0)
+    }
+
+    pub fn state(&self) -> PlayerState {
+        PlayerState {
+            pos: self.pos,
+            vel: self.vel,
+            eyeang: self.eyeang,
+        }
+    }
+}
+
+/// What rendering needs from a player, so it can blend between ticks.
+#[derive(Copy, Clone, Debug)]
+pub struct PlayerState {
+    pub pos: na::Pnt3<f32>,
+    pub vel: na::Vec3<f32>,
+    pub eyeang: na::Vec3<f32>,
+}
+impl PlayerState {
+    /// Blends from this state to `next`; `t` runs from 0 to 1.
+    pub fn lerp(&self, next: &PlayerState, t: f32) -> PlayerState {
+        PlayerState {
+            pos: self.pos + (next.pos - self.pos) * t,
+            vel: self.vel + (next.vel - self.vel) * t,
+            eyeang: na::Vec3::new(
+                self.eyeang.x + (next.eyeang.x - self.eyeang.x) * t,
+                lerp_angle(self.eyeang.y, next.eyeang.y, t),
+                self.eyeang.z + (next.eyeang.z - self.eyeang.z) * t),
+        }
+    }
+}
+
+/// Blends between two angles the short way around.
+fn lerp_angle(a: f32, b: f32, t: f32) -> f32 {
+    let mut diff = b - a;
+    if diff > PI {
+        diff -= PI_2;
+    } else if diff < -PI {
+        diff += PI_2;
+    }
+    a + diff * t
+}
+
+/// Sine and cosine using only adds and multiplies in a fixed order, so movement comes out
+/// the same with every build and C library. Good to about 1e-6 for angles a player can face.
+pub fn sin_cos(a: f32) -> (f32, f32) {
+    // Take out whole quarter turns, leaving an angle in [-pi/4, pi/4] for the series.
+    let quarters = (a * FRAC_2_PI).round();
+    let x = a - quarters * FRAC_PI_2;
+    let x2 = x * x;
+    let s = x * (1.0 - x2 * (1.0 / 6.0 - x2 * (1.0 / 120.0 - x2 * (1.0 / 5040.0))));
+    let c = 1.0 - x2 * (0.5 - x2 * (1.0 / 24.0 - x2 * (1.0 / 720.0 - x2 * (1.0 / 40320.0))));
+    match (quarters as i64) & 3 {
+        0 => (s, c),
+        1 => (c, -s),
+        2 => (-s, -c),
+        _ => (-c, s)
+    }
+}
+
+/// Turns `v` about the vertical axis by `yaw`.
+pub fn yaw_rotate(v: &na::Vec3<f32>, yaw: f32) -> na::Vec3<f32> {
+    let (s, c) = sin_cos(yaw);
+    na::Vec3::new(v.x * c + v.z * s, v.y, v.z * c - v.x * s)
+}
+
+/// The direction a player with the given view angles is looking.
+pub fn view_forward(eyeang: &na::Vec3<f32>) -> na::Vec3<f32> {
+    let (pitchsin, pitchcos) = sin_cos(eyeang.x);
+    yaw_rotate(&na::Vec3::new(0.0, pitchsin, -pitchcos), eyeang.y)
 }
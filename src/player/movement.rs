@@ -1,23 +1,47 @@
 use map::cast::{
     Ray,
-    CastResult
+    CastResult,
+    combine_results
 };
 use map::{EntityKind, Map};
+use map::bsp::{Brush, SURF_LADDER, MASK_SOLID, MASK_PLAYERSOLID};
 use player::{
+    self,
     Player,
     PlayerFlags,
     PLAYER_ONGROUND,
     PLAYER_HOLDING_JUMP,
     PLAYER_CAN_STEP,
     PLAYER_MUST_DIE,
+    PLAYER_CROUCHED,
+    PLAYER_SLIDING,
+    PLAYER_WALLRUNNING,
+    PLAYER_WALLRUN_SPENT,
+    PLAYER_JUMPED,
+    PLAYER_HOLDING_HOOK,
+    PLAYER_ONLADDER,
+    PLAYER_ONRAMP,
 };
-use na::{
-    self,
-    Rotation
-};
+use na;
+use weapon::WeaponKind;
 use Game;
-use std::f32::consts::PI;
+use std::f32::consts::{PI, PI_2};
+
+/// Things that happened to a player during a move, for effects to react to.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum MoveEvent {
+    /// Hit the ground, falling at this speed.
+    Landed(f32),
+    Jumped,
+    /// Climbed a step this tall.
+    SteppedUp(f32),
+    /// Ran into a wall with this normal, at this speed into it.
+    HitWall(na::Vec3<f32>, f32),
+    /// Landed crouched and started sliding.
+    Slid,
+}
 
+#[derive(Clone)]
 pub struct MoveInput {
     /// The velocity the player "wishes" to have 
     pub wishvel: na::Vec3<f32>,
@@ -25,11 +49,75 @@ pub struct MoveInput {
     pub eyeang: na::Vec3<f32>,
 
     pub jump: bool,
+    pub crouch: bool,
+    pub hook: bool,
     pub reset: bool,
+
+    pub fire: Option<WeaponKind>,
 }
+impl MoveInput {
+    /// Standing still, looking straight ahead.
+    pub fn idle() -> MoveInput {
+        MoveInput {
+            wishvel: na::zero(),
+            eyeang: na::zero(),
+            jump: false,
+            crouch: false,
+            hook: false,
+            reset: false,
+            fire: None,
+        }
+    }
+
+    /// What to run again when the next input hasn't turned up: keep moving, looking,
+    /// crouching and holding the hook, but don't press anything a second time.
+    pub fn repeated(&self) -> MoveInput {
+        MoveInput {
+            jump: false,
+            reset: false,
+            fire: None,
+            .. self.clone()
+        }
+    }
+
+    /// The same input with its view angles rounded to 16 bits, like Q3 sends them.
+    pub fn quantized(&self) -> MoveInput {
+        let quantize = |a: f32| {
+            let steps = 65536.0 / PI_2;
+            (a * steps).round() / steps
+        };
+        MoveInput {
+            eyeang: na::Vec3::new(quantize(self.eyeang.x), quantize(self.eyeang.y), quantize(self.eyeang.z)),
+            .. self.clone()
+        }
+    }
+}
+
+/// Everything a player's traces can hit: the map, and other players if they collide.
+struct World<'a> {
+    map: &'a Map,
+    players: Vec<Brush>,
+}
+impl<'a> World<'a> {
+    fn cast_ray(&self, ray: &Ray) -> Option<CastResult> {
+        let mut best = self.map.cast_ray(ray, MASK_PLAYERSOLID);
+        for brush in &self.players {
+            best = combine_results(best, brush.cast_ray(ray, (0.0, 1.0), MASK_PLAYERSOLID));
+        }
+        best
+    }
 
-fn simple_move(map: &Map, pl: &mut Player, dt: f32) {
+    /// Whether a box has room to be here, clear of the map and of other players.
+    fn box_fits(&self, center: &na::Pnt3<f32>, halfextents: &na::Vec3<f32>) -> bool {
+        !self.map.box_blocked(center, halfextents) &&
+            !self.players.iter().any(|brush| brush.overlaps_box(center, halfextents))
+    }
+}
+
+/// Returns the last entity the player ran into, if any.
+fn simple_move(world: &World, pl: &mut Player, dt: f32) -> Option<u32> {
     let mut dt = dt;
+    let mut touched = None;
     let mut numcontacts = 0;
     let mut contacts: [na::Vec3<f32>; 4] = [na::zero(); 4]; 
     let mut v = pl.vel;
@@ -45,14 +133,15 @@ fn simple_move(map: &Map, pl: &mut Player, dt: f32) {
             halfextents: pl.halfextents
         };
 
-        let cast = map.cast_ray(&moveray);
+        let cast = world.cast_ray(&moveray);
 
         if let Some(CastResult { toi, norm, entity, .. }) = cast {
             if let Some(entidx) = entity {
-                if map.entities[entidx as usize].kind == EntityKind::OutOfBounds {
+                touched = Some(entidx);
+                if world.map.entities[entidx as usize].kind == EntityKind::OutOfBounds {
                     pl.flags.insert(PLAYER_MUST_DIE);
                 }
-                if map.entities[entidx as usize].kind == EntityKind::Goal {
+                if world.map.entities[entidx as usize].kind == EntityKind::Goal {
                     panic!("A WINNER IS YOU!");
                 }
             }
@@ -68,6 +157,13 @@ fn simple_move(map: &Map, pl: &mut Player, dt: f32) {
                 numcontacts += 1;
             }
             contacts[numcontacts - 1] = norm;
+            if is_wall(&norm) {
+                pl.wallnormal = Some(norm);
+                let speed = -na::dot(&v, &norm);
+                if speed > 0.0 {
+                    pl.events.push(MoveEvent::HitWall(norm, speed));
+                }
+            }
 
             let mut bad = false;
             for i in 0..numcontacts {
@@ -105,10 +201,11 @@ fn simple_move(map: &Map, pl: &mut Player, dt: f32) {
         }
     }
     pl.vel = v;
+    touched
 }
 
-fn how_far(map: &Map, pl: &Player, movement: na::Vec3<f32>) -> (na::Vec3<f32>, Option<na::Vec3<f32>>) {
-    let trace = map.cast_ray(&Ray {
+fn how_far(world: &World, pl: &Player, movement: na::Vec3<f32>) -> (na::Vec3<f32>, Option<na::Vec3<f32>>) {
+    let trace = world.cast_ray(&Ray {
         orig: pl.pos,
         dir: movement,
         halfextents: pl.halfextents
@@ -119,10 +216,101 @@ fn how_far(map: &Map, pl: &Player, movement: na::Vec3<f32>) -> (na::Vec3<f32>, O
         (pl.pos.to_vec() + movement, None) 
     }
 }
+/// How far below the player to look for ground.
+const GROUND_TRACE: f32 = 0.25;
+
+/// Works out what the player is standing on, recording it on the player.
+/// Returns whether it's ground they can walk on; anything steeper is a ramp they slide down.
+fn categorize_position(world: &World, pl: &mut Player, slopelimit: f32) -> bool {
+    let cast = world.cast_ray(&Ray {
+        orig: pl.pos,
+        dir: na::Vec3::new(0.0, GROUND_TRACE, 0.0),
+        halfextents: pl.halfextents
+    });
+
+    pl.flags.remove(PLAYER_ONRAMP);
+    let hit = match cast {
+        Some(hit) => hit,
+        None => {
+            pl.groundnormal = None;
+            pl.groundentity = None;
+            pl.groundflags = 0;
+            return false;
+        }
+    };
+    pl.groundnormal = Some(hit.norm);
+    pl.groundflags = hit.flags;
+
+    // Moving up and away from the surface, like just after a jump.
+    if pl.vel.y < 0.0 && na::dot(&pl.vel, &hit.norm) > 10.0 {
+        pl.groundentity = None;
+        return false;
+    }
+
+    if hit.norm.y > -slopelimit {
+        pl.flags.insert(PLAYER_ONRAMP);
+        pl.groundentity = None;
+        return false;
+    }
+
+    pl.groundentity = hit.entity;
+    true
+}
+
+/// Surfing players only feel the part of gravity that runs along the ramp.
+fn apply_gravity(pl: &mut Player, gravity: f32, dt: f32, surf: bool) {
+    if pl.flags.contains(PLAYER_ONGROUND) || pl.flags.contains(PLAYER_ONLADDER) {
+        return;
+    }
+    let mut g = na::Vec3::new(0.0, gravity * dt, 0.0);
+    match pl.groundnormal {
+        Some(norm) if surf && pl.flags.contains(PLAYER_ONRAMP) => clip_velocity(&mut g, &norm, 1.0),
+        _ => ()
+    }
+    pl.vel = pl.vel + g;
+}
+
+/// Near-vertical surfaces can be wall-run on and jumped off.
+fn is_wall(norm: &na::Vec3<f32>) -> bool {
+    na::abs(&norm.y) < 0.3
+}
+/// Looks for a ladder just in front of the player, returning its normal.
+fn find_ladder(world: &World, pl: &Player) -> Option<na::Vec3<f32>> {
+    let forward = player::yaw_rotate(&na::Vec3::new(0.0, 0.0, -1.0), pl.eyeang.y);
+    let cast = world.cast_ray(&Ray {
+        orig: pl.pos,
+        dir: forward,
+        halfextents: pl.halfextents
+    });
+    match cast {
+        Some(hit) if hit.flags & SURF_LADDER != 0 => Some(hit.norm),
+        _ => None
+    }
+}
 fn horiz_speed(vel: &na::Vec3<f32>) -> f32 {
     na::norm(&na::Vec2::new(vel.x, vel.z))
 }
 
+/// Turns horizontal velocity toward `wishdir` without changing its speed.
+/// The closer the two already are, the harder it turns.
+fn air_control(vel: &mut na::Vec3<f32>, wishdir: &na::Vec3<f32>, control: f32, dt: f32) {
+    let mut horiz = na::Vec3::new(vel.x, 0.0, vel.z);
+    let speed = na::norm(&horiz);
+    if na::approx_eq(&speed, &0.0) {
+        return;
+    }
+    horiz = horiz / speed;
+
+    let dot = na::dot(&horiz, wishdir);
+    if dot > 0.0 {
+        let k = 32.0 * control * dot * dot * dt;
+        horiz = na::normalize(&(horiz * speed + *wishdir * k));
+    }
+
+    vel.x = horiz.x * speed;
+    vel.z = horiz.z * speed;
+}
+
 fn decay_punch_component(c: f32, dt: f32, scale: f32) -> f32 {
     if c >= 0.001 {
         let k = c * 2.0f32.powf(-1.0 * scale * dt);
@@ -151,9 +339,67 @@ fn decay_punch(punch: na::Vec3<f32>, dt: f32, scale: f32) -> na::Vec3<f32> {
 
 
 
+/// Runs the movers, carrying their riders and shoving anyone in their way.
+/// A mover that can't shove someone clear goes back the way it came instead.
+pub fn update_movers(game: &mut Game, dt: f32) {
+    for entidx in 0..game.map.entities.len() {
+        let (before, delta) = match game.map.entities[entidx].mover {
+            Some(ref mut mover) => {
+                let before = mover.clone();
+                mover.update(game.time, dt);
+                (before, mover.delta)
+            },
+            None => continue
+        };
+        if na::approx_eq(&delta, &na::zero()) {
+            continue;
+        }
+
+        if !push_players(game, entidx as u32, delta) {
+            if let Some(ref mut mover) = game.map.entities[entidx].mover {
+                *mover = before;
+                mover.delta = na::zero();
+                mover.reverse();
+            }
+        }
+    }
+}
+
+/// Moves everyone standing on or caught inside an entity along by `delta`.
+/// Nobody moves unless everyone has room to.
+fn push_players(game: &mut Game, entidx: u32, delta: na::Vec3<f32>) -> bool {
+    let mut pushed = vec![];
+    for (idx, pl) in game.players.iter().enumerate() {
+        if pl.groundentity != Some(entidx) && !game.map.entity_overlaps(entidx, &pl.pos, &pl.halfextents) {
+            continue;
+        }
+        if game.map.box_blocked(&(pl.pos + delta), &pl.halfextents) {
+            return false;
+        }
+        pushed.push(idx);
+    }
+    for idx in pushed {
+        let pl = &mut game.players[idx];
+        pl.pos = pl.pos + delta;
+    }
+    true
+}
+
 pub fn move_player(game: &mut Game, playeridx: u32, input: &MoveInput, dt: f32) {
+    let others: Vec<Brush> = if game.mode.players_collide() {
+        game.players.iter().enumerate()
+            .filter(|&(idx, _)| idx as u32 != playeridx)
+            .map(|(_, other)| Brush::from_box(&other.pos, &other.halfextents))
+            .collect()
+    } else {
+        vec![]
+    };
+
+    let touched;
     {
+        let world = World { map: &game.map, players: others };
         let pl = &mut game.players[playeridx as usize];
+        pl.events.clear();
         pl.viewpunch = pl.viewpunch + pl.viewpunch_vel * dt;
         pl.viewpunch = decay_punch(pl.viewpunch, dt, 10.0); 
         pl.viewpunch_vel = decay_punch(pl.viewpunch_vel, dt, 28.0); 
@@ -164,56 +410,164 @@ pub fn move_player(game: &mut Game, playeridx: u32, input: &MoveInput, dt: f32)
             pl.pos = na::Pnt3::new(0.0, 0.0, 0.0);
             pl.eyeang = na::Vec3::new(0.0, 0.0, 0.0);
             pl.vel = na::zero();
+            pl.set_hull(&game.movesettings.hull);
             pl.flags = PlayerFlags::empty(); 
             // FIXME: need a better way to handle this
             // without this, you slide when respawning
             pl.flags.insert(PLAYER_ONGROUND);
+            pl.groundentity = None;
+            pl.groundnormal = None;
+            pl.groundflags = 0;
+            pl.wallnormal = None;
+            pl.lastwalljump = None;
+            pl.hook = None;
+            pl.health = game.healthsettings.maxhealth;
+            pl.armor = 0.0;
+            pl.fallspeed = 0.0;
+            pl.stepchange = 0.0;
         };
 
-        if !pl.flags.contains(PLAYER_ONGROUND) {
-            pl.vel.y += game.movesettings.gravity * dt * 0.5;
-        }
-
-        let stepsize = 2.8;
-
-        let downray = Ray {
-            orig: pl.pos,
-            dir: na::Vec3::new(0.0, 0.1, 0.0),
-            halfextents: pl.halfextents
+        let gravity = if pl.flags.contains(PLAYER_WALLRUNNING) {
+            game.movesettings.gravity * game.movesettings.wallrungravity
+        } else {
+            game.movesettings.gravity
         };
 
-        let cast = game.map.cast_ray(&downray);
+        // Holding jump lets go of the ladder.
+        let ladder = find_ladder(&world, pl);
+        if ladder.is_some() && !input.jump {
+            pl.flags.insert(PLAYER_ONLADDER);
+        } else {
+            pl.flags.remove(PLAYER_ONLADDER);
+        }
 
-        let (ground_normal, hit_floor) = if let Some(CastResult { norm, ..}) = cast {
-            if norm.y < -0.7 {
-                (Some(norm), true) 
+        apply_gravity(pl, gravity, dt * 0.5, game.movesettings.surf);
+
+        if input.crouch != pl.flags.contains(PLAYER_CROUCHED) {
+            let hull = if input.crouch {
+                game.movesettings.hull.crouched()
             } else {
-                (Some(norm), false)
+                game.movesettings.hull
+            };
+            // Keep the feet where they were, and only switch if there's room, either way.
+            let mut pos = pl.pos;
+            pos.y += pl.halfextents.y - hull.halfextents.y;
+            if world.box_fits(&pos, &hull.halfextents) {
+                pl.set_hull(&hull);
+                pl.pos = pos;
+                if input.crouch {
+                    pl.flags.insert(PLAYER_CROUCHED);
+                } else {
+                    pl.flags.remove(PLAYER_CROUCHED);
+                    pl.flags.remove(PLAYER_SLIDING);
+                }
             }
-        } else {
-            (None, false)
-        };
+        }
+
+        let stepsize = 2.8;
+
+        let hit_floor = categorize_position(&world, pl, game.movesettings.slopelimit);
+        let wasonground = pl.flags.contains(PLAYER_ONGROUND);
 
         if hit_floor {
             if !pl.flags.contains(PLAYER_ONGROUND) {
                 pl.flags.insert(PLAYER_ONGROUND);
                 pl.landtime = game.time; 
+                pl.events.push(MoveEvent::Landed(pl.fallspeed));
+                let impact = pl.fallspeed - game.healthsettings.fallsafespeed;
+                if impact > 0.0 {
+                    let damage = impact * game.healthsettings.falldamage;
+                    pl.damage(damage, &game.healthsettings);
+                }
+                if pl.flags.contains(PLAYER_CROUCHED) {
+                    pl.flags.insert(PLAYER_SLIDING);
+                    pl.events.push(MoveEvent::Slid);
+                } else {
+                    pl.flags.remove(PLAYER_SLIDING);
+                }
             }
         } else {
+            if pl.flags.contains(PLAYER_ONGROUND) {
+                // Walked off a ledge rather than jumping.
+                pl.flags.remove(PLAYER_JUMPED);
+            }
             pl.flags.remove(PLAYER_ONGROUND);
         }
 
+        // Keep hold of the wall we last touched only while it's still right beside us.
+        if let Some(wallnormal) = pl.wallnormal {
+            let (_, hit) = how_far(&world, pl, wallnormal * -0.5);
+            pl.wallnormal = match hit {
+                Some(norm) if is_wall(&norm) => Some(norm),
+                _ => None
+            };
+        }
+
+        if pl.flags.contains(PLAYER_ONGROUND) {
+            pl.lastwalljump = None;
+        }
+
+        if pl.flags.contains(PLAYER_ONGROUND) || !game.movesettings.wallrun {
+            pl.flags.remove(PLAYER_WALLRUNNING);
+            pl.flags.remove(PLAYER_WALLRUN_SPENT);
+        } else if pl.wallnormal.is_some() &&
+                  horiz_speed(&pl.vel) >= game.movesettings.wallrunspeed &&
+                  !pl.flags.contains(PLAYER_WALLRUN_SPENT) {
+            if !pl.flags.contains(PLAYER_WALLRUNNING) {
+                pl.flags.insert(PLAYER_WALLRUNNING);
+                pl.wallruntime = game.time;
+                // Catch the player rather than letting them keep falling.
+                if pl.vel.y > 0.0 {
+                    pl.vel.y = 0.0;
+                }
+            } else if game.time > pl.wallruntime + game.movesettings.wallruntime {
+                pl.flags.remove(PLAYER_WALLRUNNING);
+                pl.flags.insert(PLAYER_WALLRUN_SPENT);
+            }
+        } else {
+            pl.flags.remove(PLAYER_WALLRUNNING);
+        }
+
         if input.jump { 
             if !pl.flags.contains(PLAYER_HOLDING_JUMP) || game.time < (pl.holdjumptime + game.movesettings.slidetime) {
-                if !pl.flags.contains(PLAYER_HOLDING_JUMP) {
+                let fresh = !pl.flags.contains(PLAYER_HOLDING_JUMP);
+                if fresh {
                     pl.holdjumptime = game.time;
                 }
                 if pl.flags.contains(PLAYER_ONGROUND) {
                     pl.flags.remove(PLAYER_ONGROUND);
-                    let jspeed = game.movesettings.jumpspeed;
-
-                    pl.vel.y += -jspeed;
-                    if pl.vel.y > -jspeed { pl.vel.y = -jspeed; }
+                    let mut jspeed = game.movesettings.jumpspeed;
+                    if game.movesettings.doublejump && pl.flags.contains(PLAYER_JUMPED) &&
+                       game.time < pl.landtime + game.movesettings.doublejumptime {
+                        jspeed += game.movesettings.doublejumpspeed;
+                    }
+                    pl.flags.insert(PLAYER_JUMPED);
+                    pl.events.push(MoveEvent::Jumped);
+
+                    if game.movesettings.rampjump {
+                        // Keep whatever upward speed the surface was giving us.
+                        pl.vel.y += -jspeed;
+                        if pl.vel.y > -jspeed { pl.vel.y = -jspeed; }
+                    } else {
+                        pl.vel.y = -jspeed;
+                    }
+                } else if fresh && ladder.is_some() {
+                    let laddernormal = ladder.unwrap();
+                    pl.vel = laddernormal * game.movesettings.ladderjumpspeed;
+                } else if fresh && game.movesettings.wallrun {
+                    let again = match (pl.wallnormal, pl.lastwalljump) {
+                        (Some(wallnormal), Some(last)) => na::approx_eq(&wallnormal, &last),
+                        _ => false
+                    };
+                    if let (Some(wallnormal), false) = (pl.wallnormal, again) {
+                        pl.lastwalljump = Some(wallnormal);
+                        pl.vel = pl.vel + wallnormal * game.movesettings.walljumpspeed;
+                        pl.vel.y = -game.movesettings.jumpspeed;
+                        pl.events.push(MoveEvent::Jumped);
+                        pl.wallnormal = None;
+                        pl.flags.remove(PLAYER_WALLRUNNING);
+                        pl.flags.remove(PLAYER_WALLRUN_SPENT);
+                    }
                 }
                 pl.flags.insert(PLAYER_HOLDING_JUMP);
             }
@@ -221,19 +575,50 @@ pub fn move_player(game: &mut Game, playeridx: u32, input: &MoveInput, dt: f32)
             pl.flags.remove(PLAYER_HOLDING_JUMP);
         }
 
-        let accel = if pl.flags.contains(PLAYER_ONGROUND) && game.time > (pl.landtime + game.movesettings.slidetime) {
+        if input.hook && game.movesettings.hook {
+            if !pl.flags.contains(PLAYER_HOLDING_HOOK) {
+                let eye = pl.eyepos();
+                let dir = player::view_forward(&pl.eyeang) * game.movesettings.hookrange;
+                let cast = game.map.cast_ray(&Ray {
+                    orig: eye,
+                    dir: dir,
+                    halfextents: na::zero()
+                }, MASK_SOLID);
+                if let Some(hit) = cast {
+                    let anchor = eye + dir * hit.toi;
+                    pl.hook = Some(anchor);
+                    pl.hooklength = na::norm(&(pl.pos.to_vec() - anchor.to_vec()));
+                }
+                pl.flags.insert(PLAYER_HOLDING_HOOK);
+            }
+        } else {
+            pl.hook = None;
+            pl.flags.remove(PLAYER_HOLDING_HOOK);
+        }
+
+        let slidetime = if pl.flags.contains(PLAYER_SLIDING) {
+            game.movesettings.crouchslidetime
+        } else {
+            game.movesettings.slidetime
+        };
+
+        let accel = if pl.flags.contains(PLAYER_ONGROUND) && game.time > (pl.landtime + slidetime) {
             game.movesettings.accel
         } else {
             game.movesettings.airaccel
         };
-        let friction = if pl.flags.contains(PLAYER_ONGROUND) && game.time > (pl.landtime + game.movesettings.slidetime) { 
+        let friction = if pl.flags.contains(PLAYER_ONGROUND) && game.time > (pl.landtime + slidetime) { 
             game.movesettings.friction 
         } else {
             0.0
         };
 
-        let speedcap = if pl.flags.contains(PLAYER_ONGROUND) && game.time > (pl.landtime + game.movesettings.slidetime) { 
-            game.movesettings.movespeed
+        let speedcap = if pl.flags.contains(PLAYER_ONGROUND) && game.time > (pl.landtime + slidetime) { 
+            if pl.flags.contains(PLAYER_CROUCHED) {
+                game.movesettings.crouchspeed
+            } else {
+                game.movesettings.movespeed
+            }
         } else {
             game.movesettings.airspeed
         };
@@ -255,14 +640,24 @@ pub fn move_player(game: &mut Game, playeridx: u32, input: &MoveInput, dt: f32)
             pl.vel = dir * newspeed;
         }
 
-        let rot = na::Rot3::new(na::Vec3::new(0.0, input.eyeang.y, 0.0));
-        let mut wishvel = na::rotate(
-            &rot,
-            &input.wishvel);
-        if let Some(ground_normal) = ground_normal {
-            clip_velocity(&mut wishvel, &ground_normal, 1.0); 
+        let mut wishvel = player::yaw_rotate(&input.wishvel, input.eyeang.y);
+        if let Some(groundnormal) = pl.groundnormal {
+            clip_velocity(&mut wishvel, &groundnormal, 1.0); 
         }
 
+        // CPM treats holding only forward/back and holding only strafe differently in the air.
+        let airborne = !pl.flags.contains(PLAYER_ONGROUND);
+        let forwardonly = input.wishvel.x == 0.0 && input.wishvel.z != 0.0;
+        let sideonly = input.wishvel.z == 0.0 && input.wishvel.x != 0.0;
+        let surfing = game.movesettings.surf && pl.flags.contains(PLAYER_ONRAMP);
+        let (accel, speedcap) = if surfing {
+            (game.movesettings.surfaccel, game.movesettings.airspeed)
+        } else if airborne && sideonly {
+            (game.movesettings.airstrafeaccel, game.movesettings.airstrafespeed)
+        } else {
+            (accel, speedcap)
+        };
+
         let real_wishspeed = na::clamp(na::norm(&wishvel), 0.0, game.movesettings.movespeed);
         let wishspeed = na::clamp(real_wishspeed, 0.0, speedcap);
         if !na::approx_eq(&wishspeed, &0.0) { 
@@ -270,25 +665,82 @@ pub fn move_player(game: &mut Game, playeridx: u32, input: &MoveInput, dt: f32)
             let movedir = na::normalize(&wishvel);
 
             let curspeed = na::dot(&pl.vel, &movedir); 
-            let maxdelta = accel * real_wishspeed * dt; 
+            let accel = if airborne && !surfing && !sideonly && curspeed < 0.0 {
+                game.movesettings.airstopaccel
+            } else {
+                accel
+            };
+            // CPM's strafe-only accel scales with the capped speed, not the speed you asked for.
+            let capped = game.movesettings.cappedstrafeaccel && airborne && sideonly && !surfing;
+            let accelspeed = if capped { wishspeed } else { real_wishspeed };
+            let maxdelta = accel * accelspeed * dt;
             let addspeed = na::clamp(wishspeed - curspeed, 0.0, maxdelta);
             pl.vel = pl.vel + (movedir * addspeed);
+
+            if airborne && forwardonly && game.movesettings.aircontrol > 0.0 {
+                air_control(&mut pl.vel, &movedir, game.movesettings.aircontrol, dt);
+            }
+        }
+
+        match ladder {
+            Some(laddernormal) if pl.flags.contains(PLAYER_ONLADDER) => {
+                // Forward climbs at full speed looking level or up. Looking down slows it,
+                // stopping at about 0.4 radians down and climbing down past that.
+                let (pitchsin, _) = player::sin_cos(input.eyeang.x);
+                let climb = na::clamp(1.0 - pitchsin * 2.5, -1.0, 1.0);
+                let forward = -input.wishvel.z / game.movesettings.movespeed;
+                let mut side = player::yaw_rotate(&na::Vec3::new(input.wishvel.x, 0.0, 0.0), input.eyeang.y);
+                clip_velocity(&mut side, &laddernormal, 1.0);
+                side = side * (game.movesettings.ladderspeed / game.movesettings.movespeed);
+                pl.vel = side + na::Vec3::new(0.0, -forward * climb * game.movesettings.ladderspeed, 0.0);
+            },
+            _ => ()
+        }
+
+        if let Some(anchor) = pl.hook {
+            pl.hooklength = na::clamp(pl.hooklength - game.movesettings.hookpullspeed * dt,
+                                      game.movesettings.hookminlength,
+                                      game.movesettings.hookrange);
+            let offset = pl.pos.to_vec() - anchor.to_vec();
+            let dist = na::norm(&offset);
+            if dist > pl.hooklength {
+                // The rope is taut: move along it only as fast as gets us back to its length
+                // over this tick. Speed across the rope is left alone, so we swing.
+                let dir = offset / dist;
+                let outward = na::dot(&pl.vel, &dir);
+                let wanted = -(dist - pl.hooklength) / dt;
+                if outward > wanted {
+                    pl.vel = pl.vel - dir * (outward - wanted);
+                }
+            }
+        }
+
+        if !pl.flags.contains(PLAYER_ONGROUND) {
+            pl.fallspeed = pl.vel.y;
         }
 
+        // Both tries below record events; keep only the ones from the try that wins.
+        // The same goes for the wall they leave us up against.
+        let nevents = pl.events.len();
         let startpos = pl.pos;
         let startvel = pl.vel;
-        simple_move(&game.map, pl, dt);
+        let startwall = pl.wallnormal;
+        let downtouched = simple_move(&world, pl, dt);
 
         let downpos = pl.pos;
         let downvel = pl.vel;
+        let downwall = pl.wallnormal;
+        let downevents = pl.events.split_off(nevents);
 
         pl.pos = startpos;
         pl.vel = startvel;
-        let (upstart, _) = how_far(&game.map, pl, na::Vec3::new(0.0, -stepsize, 0.0));
+        pl.wallnormal = startwall;
+        let (upstart, _) = how_far(&world, pl, na::Vec3::new(0.0, -stepsize, 0.0));
         pl.pos = upstart.to_pnt();
-        simple_move(&game.map, pl, dt);
+        let uptouched = simple_move(&world, pl, dt);
+        let upevents = pl.events.split_off(nevents);
 
-        let (downstart, landnorm) = how_far(&game.map, pl, na::Vec3::new(0.0, stepsize , 0.0));
+        let (downstart, landnorm) = how_far(&world, pl, na::Vec3::new(0.0, stepsize , 0.0));
         pl.pos = downstart.to_pnt(); 
 
         let updist = horiz_speed(&(pl.pos.to_vec() - startpos.to_vec()));
@@ -310,12 +762,55 @@ pub fn move_player(game: &mut Game, playeridx: u32, input: &MoveInput, dt: f32)
         if !stepped {
             pl.pos = downpos;
             pl.vel = downvel;
+            pl.wallnormal = downwall;
+            pl.events.extend(downevents.into_iter());
+            touched = downtouched.or(pl.groundentity);
+        } else {
+            pl.events.extend(upevents.into_iter());
+            let height = startpos.y - pl.pos.y;
+            if height > 0.0 {
+                // Carry over what's left of the last step, so stairs are one smooth climb.
+                pl.stepchange = pl.step_offset(game.time) + height;
+                pl.steptime = game.time;
+                pl.events.push(MoveEvent::SteppedUp(height));
+            }
+            touched = uptouched.or(pl.groundentity);
         }
 
-        if !pl.flags.contains(PLAYER_ONGROUND) {
-            pl.vel.y += game.movesettings.gravity * dt * 0.5;
+        // Stick to the ground walking down slopes and stairs, rather than bouncing off into the air.
+        if wasonground && pl.flags.contains(PLAYER_ONGROUND) && !pl.flags.contains(PLAYER_ONLADDER) {
+            let (_, below) = how_far(&world, pl, na::Vec3::new(0.0, GROUND_TRACE, 0.0));
+            if below.is_none() {
+                let (snapped, norm) = how_far(&world, pl, na::Vec3::new(0.0, stepsize, 0.0));
+                match norm {
+                    Some(norm) if norm.y < -game.movesettings.slopelimit => {
+                        pl.pos = snapped.to_pnt();
+                        clip_velocity(&mut pl.vel, &norm, 1.0);
+                    },
+                    _ => ()
+                }
+            }
         }
 
+        apply_gravity(pl, gravity, dt * 0.5, game.movesettings.surf);
+
+        if game.movesettings.snapvelocity {
+            // Q3 rounds velocity to whole units every frame, which is why
+            // jump heights there depend on the framerate.
+            pl.vel = na::Vec3::new(pl.vel.x.round(), pl.vel.y.round(), pl.vel.z.round());
+        }
+
+        for entidx in game.map.touching(&pl.pos, &pl.halfextents) {
+            if let EntityKind::Hazard(dps) = game.map.entities[entidx as usize].kind {
+                pl.damage(dps * dt, &game.healthsettings);
+            }
+        }
+    }
+
+    if let Some(touched) = touched {
+        if let Some(ref mut mover) = game.map.entities[touched as usize].mover {
+            mover.trigger(game.time);
+        }
     }
 }
     
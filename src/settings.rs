@@ -1,8 +1,66 @@
-use glutin::VirtualKeyCode;
+use glutin::{VirtualKeyCode, MouseButton};
+use player::Hull;
+use weapon::WeaponKind;
 use std;
 
+/// Named physics presets. Records are only comparable within one.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum PhysicsMode {
+    Vel0city,
+    Vq3,
+    Cpm,
+}
+impl PhysicsMode {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            PhysicsMode::Vel0city => "vel0city",
+            PhysicsMode::Vq3 => "vq3",
+            PhysicsMode::Cpm => "cpm",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<PhysicsMode> {
+        [PhysicsMode::Vel0city, PhysicsMode::Vq3, PhysicsMode::Cpm].iter()
+            .cloned()
+            .find(|mode| mode.name() == name)
+    }
+
+    /// The mode after this one, for cycling through them at runtime.
+    pub fn next(&self) -> PhysicsMode {
+        match *self {
+            PhysicsMode::Vel0city => PhysicsMode::Vq3,
+            PhysicsMode::Vq3 => PhysicsMode::Cpm,
+            PhysicsMode::Cpm => PhysicsMode::Vel0city,
+        }
+    }
+}
+impl std::fmt::Display for PhysicsMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// How players share the map.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum GameMode {
+    /// Players pass through each other.
+    Race,
+    /// Players are solid, so they can boost and stack on each other.
+    Coop,
+}
+impl GameMode {
+    pub fn players_collide(&self) -> bool {
+        match *self {
+            GameMode::Race => false,
+            GameMode::Coop => true,
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct MoveSettings {
+    /// Which preset these settings came from.
+    pub mode: PhysicsMode,
     /// The acceleration due to gravity.
     pub gravity: f32,
     /// How fast players can accelerate
@@ -17,16 +75,89 @@ pub struct MoveSettings {
     pub movespeed: f32,
     
     pub airspeed: f32,
+    /// Midair acceleration when pushing against the current velocity.
+    pub airstopaccel: f32,
+    /// Midair acceleration when only strafing, with no forward/back held.
+    pub airstrafeaccel: f32,
+    /// Speed cap that goes with `airstrafeaccel`.
+    pub airstrafespeed: f32,
+    /// Whether `airstrafeaccel` scales with `airstrafespeed` rather than the speed asked for, like CPM.
+    pub cappedstrafeaccel: bool,
+    /// How strongly holding only forward/back steers velocity toward the view in midair.
+    /// Zero turns air control off.
+    pub aircontrol: f32,
     
     pub jumpspeed: f32,
+    /// Whether jumping again soon after landing from a jump goes higher.
+    pub doublejump: bool,
+    /// Extra upward speed for a double-jump.
+    pub doublejumpspeed: f32,
+    /// How long after landing a double-jump is allowed.
+    pub doublejumptime: f32,
+    /// Whether jumping off a surface that's carrying the player upward keeps that speed.
+    pub rampjump: bool,
 
     pub friction: f32,
 
     pub slidetime: f32,
+
+    /// Surfaces whose normal points up less than this are too steep to walk on.
+    pub slopelimit: f32,
+    /// Whether ramps too steep to walk on can be surfed: no friction,
+    /// gravity only along the ramp, and air strafing with `surfaccel`.
+    pub surf: bool,
+    pub surfaccel: f32,
+
+    /// The player's collision box while standing. Crouching uses a shorter one of the same width.
+    pub hull: Hull,
+    /// Maximum ground speed while crouched.
+    pub crouchspeed: f32,
+    /// Replaces `slidetime` when landing while crouched.
+    pub crouchslidetime: f32,
+
+    /// Whether players can run along walls.
+    pub wallrun: bool,
+    /// Minimum horizontal speed to start a wall-run.
+    pub wallrunspeed: f32,
+    /// Longest a single wall-run can last.
+    pub wallruntime: f32,
+    /// Gravity is scaled by this while wall-running.
+    pub wallrungravity: f32,
+    /// How hard a wall-jump pushes away from the wall.
+    pub walljumpspeed: f32,
+
+    /// Whether players have a grappling hook.
+    pub hook: bool,
+    /// How far the hook reaches.
+    pub hookrange: f32,
+    /// How fast the rope reels in while held.
+    pub hookpullspeed: f32,
+    /// The rope never reels in shorter than this.
+    pub hookminlength: f32,
+
+    /// Climbing speed on ladders.
+    pub ladderspeed: f32,
+    /// How hard jumping pushes off a ladder.
+    pub ladderjumpspeed: f32,
+
+    /// Round velocity to whole units after every move, like Q3 does.
+    pub snapvelocity: bool,
+    /// If nonzero, ticks last a whole number of milliseconds as they would at this
+    /// com_maxfps in Q3, instead of following the tick rate.
+    pub maxfps: u32,
 }
-impl std::default::Default for MoveSettings {
-    fn default() -> MoveSettings {
+impl MoveSettings {
+    pub fn preset(mode: PhysicsMode) -> MoveSettings {
+        match mode {
+            PhysicsMode::Vel0city => MoveSettings::vel0city(),
+            PhysicsMode::Vq3 => MoveSettings::vq3(),
+            PhysicsMode::Cpm => MoveSettings::cpm(),
+        }
+    }
+
+    pub fn vel0city() -> MoveSettings {
         MoveSettings {
+            mode: PhysicsMode::Vel0city,
             gravity: 550.0,
             accel: 18.0,
             airaccel: 3.2,
@@ -34,21 +165,325 @@ impl std::default::Default for MoveSettings {
             maxspeed: 1000.0,
             movespeed: 220.0,
             airspeed: 55.0,
+            airstopaccel: 3.2,
+            airstrafeaccel: 3.2,
+            airstrafespeed: 55.0,
+            cappedstrafeaccel: false,
+            aircontrol: 0.0,
             jumpspeed: 220.0,
+            doublejump: false,
+            doublejumpspeed: 100.0,
+            doublejumptime: 0.4,
+            rampjump: true,
             friction: 8.0, 
             slidetime: 0.16,
+            slopelimit: 0.7,
+            surf: true,
+            surfaccel: 50.0,
+            hull: Hull::standing(),
+            crouchspeed: 80.0,
+            crouchslidetime: 0.6,
+            wallrun: true,
+            wallrunspeed: 200.0,
+            wallruntime: 1.2,
+            wallrungravity: 0.25,
+            walljumpspeed: 250.0,
+            hook: true,
+            hookrange: 1024.0,
+            hookpullspeed: 200.0,
+            hookminlength: 32.0,
+            ladderspeed: 200.0,
+            ladderjumpspeed: 200.0,
+            snapvelocity: false,
+            maxfps: 0,
+        }
+    }
+
+    /// Quake 3 as shipped.
+    pub fn vq3() -> MoveSettings {
+        MoveSettings {
+            mode: PhysicsMode::Vq3,
+            gravity: 800.0,
+            accel: 10.0,
+            airaccel: 1.0,
+            speedeps: 100.0,
+            maxspeed: 1000.0,
+            movespeed: 320.0,
+            airspeed: 320.0,
+            airstopaccel: 1.0,
+            airstrafeaccel: 1.0,
+            airstrafespeed: 320.0,
+            cappedstrafeaccel: false,
+            aircontrol: 0.0,
+            jumpspeed: 270.0,
+            doublejump: false,
+            doublejumpspeed: 100.0,
+            doublejumptime: 0.4,
+            rampjump: false,
+            friction: 6.0,
+            slidetime: 0.0,
+            slopelimit: 0.7,
+            surf: false,
+            surfaccel: 1.0,
+            hull: Hull::standing(),
+            crouchspeed: 80.0,
+            crouchslidetime: 0.0,
+            wallrun: false,
+            wallrunspeed: 200.0,
+            wallruntime: 1.2,
+            wallrungravity: 0.25,
+            walljumpspeed: 250.0,
+            hook: false,
+            hookrange: 1024.0,
+            hookpullspeed: 200.0,
+            hookminlength: 32.0,
+            ladderspeed: 200.0,
+            ladderjumpspeed: 200.0,
+            // Trick jumps in Q3 maps are usually calibrated for 125fps.
+            snapvelocity: true,
+            maxfps: 125,
+        }
+    }
+
+    /// CPMA's Promode.
+    pub fn cpm() -> MoveSettings {
+        MoveSettings {
+            mode: PhysicsMode::Cpm,
+            accel: 15.0,
+            friction: 8.0,
+            airstopaccel: 2.5,
+            airstrafeaccel: 70.0,
+            airstrafespeed: 30.0,
+            cappedstrafeaccel: true,
+            aircontrol: 150.0,
+            doublejump: true,
+            rampjump: true,
+            ..MoveSettings::vq3()
+        }
+    }
+}
+impl std::default::Default for MoveSettings {
+    fn default() -> MoveSettings {
+        MoveSettings::vel0city()
+    }
+}
+
+#[derive(Copy, Clone)]
+pub struct ProjectileSettings {
+    /// Units per second.
+    pub speed: f32,
+    /// Damage at the center of the splash, falling off linearly to the edge.
+    pub damage: f32,
+    pub splashradius: f32,
+    /// Seconds between shots.
+    pub refire: f32,
+    /// How hard firing kicks the view up.
+    pub recoil: f32,
+}
+
+#[derive(Clone)]
+pub struct WeaponSettings {
+    pub rocket: ProjectileSettings,
+    pub plasma: ProjectileSettings,
+    /// Speed added per point of splash damage.
+    pub knockback: f32,
+    /// Knockback from your own shots is scaled by this.
+    pub selfknockback: f32,
+    /// Damage from your own shots is scaled by this.
+    pub selfdamage: f32,
+}
+impl WeaponSettings {
+    pub fn get(&self, kind: WeaponKind) -> ProjectileSettings {
+        match kind {
+            WeaponKind::Rocket => self.rocket,
+            WeaponKind::Plasma => self.plasma,
+        }
+    }
+}
+impl std::default::Default for WeaponSettings {
+    fn default() -> WeaponSettings {
+        WeaponSettings {
+            rocket: ProjectileSettings {
+                speed: 900.0,
+                damage: 100.0,
+                splashradius: 120.0,
+                refire: 0.8,
+                recoil: 3.0,
+            },
+            plasma: ProjectileSettings {
+                speed: 2000.0,
+                damage: 15.0,
+                splashradius: 20.0,
+                refire: 0.1,
+                recoil: 0.3,
+            },
+            // g_knockback / player mass in Q3.
+            knockback: 5.0,
+            selfknockback: 1.0,
+            selfdamage: 0.5,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct HealthSettings {
+    pub maxhealth: f32,
+    /// Landing slower than this doesn't hurt.
+    pub fallsafespeed: f32,
+    /// Damage per unit of landing speed over `fallsafespeed`.
+    pub falldamage: f32,
+    /// Any damage at all is fatal.
+    pub hardcore: bool,
+}
+impl std::default::Default for HealthSettings {
+    fn default() -> HealthSettings {
+        HealthSettings {
+            maxhealth: 100.0,
+            fallsafespeed: 500.0,
+            falldamage: 0.1,
+            hardcore: false,
+        }
+    }
+}
+
+/// Options for making runs reproducible from their inputs alone.
+/// Movement only uses IEEE adds, multiplies, divides and square roots, in an order fixed by the
+/// code (see `player::sin_cos`), so a given input stream gives the same trajectory on any build.
+#[derive(Clone)]
+pub struct Determinism {
+    /// Ignore `timescale`, derive time from the tick count, and quantize view angles.
+    pub enabled: bool,
+    /// Round positions and velocities to a fixed grid after each tick.
+    pub snap: bool,
+}
+impl std::default::Default for Determinism {
+    fn default() -> Determinism {
+        Determinism {
+            enabled: false,
+            snap: false,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct ViewSettings {
+    /// How far the view dips per unit of landing speed.
+    pub landdip: f32,
+    /// The deepest a landing can dip the view.
+    pub maxlanddip: f32,
+    /// How hard landing nods the view down, per unit of landing speed.
+    pub landpunch: f32,
+    /// Whether to ease the view up steps instead of snapping.
+    pub stepsmoothing: bool,
+    /// How far the view bobs while running. Zero turns bobbing off.
+    pub bob: f32,
+    /// Bob cycles per unit travelled.
+    pub bobfrequency: f32,
+}
+impl std::default::Default for ViewSettings {
+    fn default() -> ViewSettings {
+        ViewSettings {
+            landdip: 0.01,
+            maxlanddip: 6.0,
+            landpunch: 0.002,
+            stepsmoothing: true,
+            bob: 0.0,
+            bobfrequency: 0.05,
         }
     }
 }
 
 pub struct InputSettings {
+    /// Whether this player looks and fires with the mouse.
+    pub mouse: bool,
     pub sensitivity: f32,
+    /// How fast the look keys turn the view, in radians per second.
+    pub turnspeed: f32,
 
     pub forwardkey: VirtualKeyCode,
     pub backkey: VirtualKeyCode,
     pub leftkey: VirtualKeyCode,
     pub rightkey: VirtualKeyCode,
     pub jumpkey: VirtualKeyCode,
+    pub crouchkey: VirtualKeyCode,
+    pub hookkey: VirtualKeyCode,
+    pub rocketbutton: MouseButton,
+    pub plasmabutton: MouseButton,
+    /// Keyboard alternatives to the fire buttons.
+    pub rocketkey: Option<VirtualKeyCode>,
+    pub plasmakey: Option<VirtualKeyCode>,
+    /// Keys for looking around without a mouse.
+    pub lookleftkey: Option<VirtualKeyCode>,
+    pub lookrightkey: Option<VirtualKeyCode>,
+    pub lookupkey: Option<VirtualKeyCode>,
+    pub lookdownkey: Option<VirtualKeyCode>,
     pub resetkey: VirtualKeyCode,
+    /// Cycles through the physics presets.
+    pub physicskey: VirtualKeyCode,
+    /// Cycles through first-person, chase and free-fly cameras.
+    pub camerakey: Option<VirtualKeyCode>,
+    /// Watches the next player along.
+    pub followkey: Option<VirtualKeyCode>,
 }
+impl InputSettings {
+    pub fn mouse_and_keyboard() -> InputSettings {
+        use glutin::VirtualKeyCode::*;
+
+        InputSettings {
+            mouse: true,
+            sensitivity: 0.001,
+            turnspeed: 3.0,
+            /*
+            forwardkey: W,
+            backkey: A,
+            leftkey: S,
+            rightkey: D,
+            */
+            forwardkey: F,
+            backkey: N,
+            leftkey: Y,
+            rightkey: E,
+            resetkey: Escape,
+            jumpkey: Space,
+            crouchkey: LControl,
+            hookkey: LShift,
+            rocketbutton: MouseButton::Left,
+            plasmabutton: MouseButton::Right,
+            rocketkey: None,
+            plasmakey: None,
+            lookleftkey: None,
+            lookrightkey: None,
+            lookupkey: None,
+            lookdownkey: None,
+            physicskey: F5,
+            camerakey: Some(F6),
+            followkey: Some(F7),
+        }
+    }
 
+    /// For a second player sharing the keyboard: moves with IJKL, looks with the arrow keys.
+    pub fn keyboard_only() -> InputSettings {
+        use glutin::VirtualKeyCode::*;
+
+        InputSettings {
+            mouse: false,
+            forwardkey: I,
+            backkey: K,
+            leftkey: J,
+            rightkey: L,
+            resetkey: Back,
+            jumpkey: RShift,
+            crouchkey: RControl,
+            hookkey: Apostrophe,
+            rocketkey: Some(Return),
+            plasmakey: Some(Semicolon),
+            lookleftkey: Some(Left),
+            lookrightkey: Some(Right),
+            lookupkey: Some(Up),
+            lookdownkey: Some(Down),
+            camerakey: Some(F8),
+            followkey: Some(F9),
+            ..InputSettings::mouse_and_keyboard()
+        }
+    }
+}
@@ -0,0 +1,70 @@
+use na;
+use player::{Player, PLAYER_ONGROUND};
+use player::movement::MoveEvent;
+use settings::ViewSettings;
+
+/// How long a landing takes to dip the view, and then to bring it back.
+const LAND_DEFLECT_TIME: f32 = 0.15;
+const LAND_RETURN_TIME: f32 = 0.3;
+
+/// Client-side camera motion driven by movement events.
+/// None of this feeds back into physics.
+pub struct ViewEffects {
+    pub settings: ViewSettings,
+
+    landtime: f32,
+    landchange: f32,
+
+    bobphase: f32,
+}
+
+impl ViewEffects {
+    pub fn new(settings: ViewSettings) -> ViewEffects {
+        ViewEffects {
+            settings: settings,
+            landtime: -1000.0,
+            landchange: 0.0,
+            bobphase: 0.0,
+        }
+    }
+
+    pub fn handle_event(&mut self, event: &MoveEvent, pl: &mut Player, time: f32) {
+        match *event {
+            MoveEvent::Landed(speed) => {
+                self.landtime = time;
+                self.landchange = na::clamp(speed * self.settings.landdip, 0.0, self.settings.maxlanddip);
+                pl.viewpunch_vel.x += speed * self.settings.landpunch;
+            },
+            _ => ()
+        }
+    }
+
+    pub fn update(&mut self, pl: &Player, dt: f32) {
+        if pl.flags.contains(PLAYER_ONGROUND) {
+            let speed = na::norm(&na::Vec2::new(pl.vel.x, pl.vel.z));
+            self.bobphase += speed * dt * self.settings.bobfrequency;
+        } else {
+            self.bobphase = 0.0;
+        }
+    }
+
+    /// Offset to add to the eye position. Positive y is down.
+    pub fn offset(&self, pl: &Player, time: f32) -> na::Vec3<f32> {
+        let mut y = if self.settings.stepsmoothing {
+            pl.step_offset(time)
+        } else {
+            0.0
+        };
+
+        let delta = time - self.landtime;
+        if delta < LAND_DEFLECT_TIME {
+            y += self.landchange * (delta / LAND_DEFLECT_TIME);
+        } else if delta < LAND_DEFLECT_TIME + LAND_RETURN_TIME {
+            y += self.landchange * (1.0 - (delta - LAND_DEFLECT_TIME) / LAND_RETURN_TIME);
+        }
+
+        y += na::abs(&self.bobphase.sin()) * self.settings.bob;
+
+        na::Vec3::new(0.0, y, 0.0)
+    }
+}
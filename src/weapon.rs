@@ -0,0 +1,117 @@
+use na;
+use std::mem;
+use map::bsp::MASK_SOLID;
+use map::cast::Ray;
+use player::{self, PLAYER_ONGROUND};
+use player::movement::MoveInput;
+use Game;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum WeaponKind {
+    Rocket,
+    Plasma,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Projectile {
+    pub kind: WeaponKind,
+    /// Id of the player who fired it.
+    pub owner: u32,
+    pub pos: na::Pnt3<f32>,
+    pub vel: na::Vec3<f32>,
+    pub spawntime: f32,
+}
+
+/// Projectiles that haven't hit anything after this long are removed.
+const PROJECTILE_LIFETIME: f32 = 10.0;
+
+pub fn fire_weapons(game: &mut Game, playeridx: u32, input: &MoveInput) {
+    let kind = match input.fire {
+        Some(kind) => kind,
+        None => return
+    };
+    let settings = game.weaponsettings.get(kind);
+    let pl = &mut game.players[playeridx as usize];
+    if pl.flags.contains(player::PLAYER_MUST_DIE) || game.time < pl.nextfire {
+        return;
+    }
+    pl.nextfire = game.time + settings.refire;
+    pl.viewpunch_vel.x -= settings.recoil;
+
+    let dir = player::view_forward(&pl.eyeang);
+    game.projectiles.push(Projectile {
+        kind: kind,
+        owner: pl.id,
+        pos: pl.eyepos(),
+        vel: dir * settings.speed,
+        spawntime: game.time,
+    });
+}
+
+pub fn update_projectiles(game: &mut Game, dt: f32) {
+    let mut explosions = vec![];
+    let projectiles = mem::replace(&mut game.projectiles, vec![]);
+    for mut proj in projectiles {
+        if game.time > proj.spawntime + PROJECTILE_LIFETIME {
+            continue;
+        }
+
+        let movement = proj.vel * dt;
+        let cast = game.map.cast_ray(&Ray {
+            orig: proj.pos,
+            dir: movement,
+            halfextents: na::zero(),
+        }, MASK_SOLID);
+        if let Some(hit) = cast {
+            // Pull back off the surface so the splash isn't inside it.
+            let pos = proj.pos + movement * hit.toi + hit.norm;
+            explosions.push((pos, proj.kind, proj.owner));
+        } else {
+            proj.pos = proj.pos + movement;
+            game.projectiles.push(proj);
+        }
+    }
+
+    for (pos, kind, owner) in explosions {
+        explode(game, pos, kind, owner);
+    }
+}
+
+/// Pushes every player within the splash radius away from `pos`.
+pub fn explode(game: &mut Game, pos: na::Pnt3<f32>, kind: WeaponKind, owner: u32) {
+    let settings = game.weaponsettings.get(kind);
+    for pl in &mut game.players {
+        // Distance to the nearest point of the player's box, like Q3.
+        let nearest = na::Pnt3::new(
+            na::clamp(pos.x, pl.pos.x - pl.halfextents.x, pl.pos.x + pl.halfextents.x),
+            na::clamp(pos.y, pl.pos.y - pl.halfextents.y, pl.pos.y + pl.halfextents.y),
+            na::clamp(pos.z, pl.pos.z - pl.halfextents.z, pl.pos.z + pl.halfextents.z));
+        let dist = na::norm(&(nearest.to_vec() - pos.to_vec()));
+        if dist >= settings.splashradius {
+            continue;
+        }
+        let points = settings.damage * (1.0 - dist / settings.splashradius);
+
+        // Aim a bit above the player's center so floor shots lift them.
+        let mut dir = pl.pos.to_vec() - pos.to_vec();
+        dir.y -= pl.halfextents.y;
+        // Straight up if the blast is right where we're aiming.
+        let dir = if na::norm(&dir) > 0.001 {
+            na::normalize(&dir)
+        } else {
+            na::Vec3::new(0.0, -1.0, 0.0)
+        };
+
+        let mut knock = na::clamp(points, 0.0, 200.0) * game.weaponsettings.knockback;
+        let mut damage = points;
+        if pl.id == owner {
+            knock *= game.weaponsettings.selfknockback;
+            damage *= game.weaponsettings.selfdamage;
+        }
+        pl.vel = pl.vel + dir * knock;
+        pl.damage(damage, &game.healthsettings);
+        if dir.y < 0.0 {
+            pl.flags.remove(PLAYER_ONGROUND);
+        }
+    }
+}
@@ -40,7 +40,8 @@ impl HudManager {
     pub fn draw_elements<S>(&self,
                             target: &mut S,
                             context: &Context,
-                            elements: &[Element]
+                            elements: &[Element],
+                            viewport: Option<glium::Rect>
                            ) where S: glium::Surface {
         for element in elements {
             match element.element_type {
@@ -60,6 +61,7 @@ impl HudManager {
                                 source: glium::LinearBlendingFactor::SourceAlpha,
                                 destination: glium::LinearBlendingFactor::OneMinusSourceAlpha,
                             }),
+                            viewport: viewport,
                             ..Default::default()
                         };
                         target.draw(&self.quad_verts,
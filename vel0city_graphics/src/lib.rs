@@ -7,6 +7,7 @@ extern crate vel0city_base;
 
 
 use glium::Surface;
+use na::ToHomogeneous;
 use map::GraphicsMap;
 use std::sync::Arc;
 use std::default::Default;
@@ -44,15 +45,17 @@ pub struct Light {
 pub struct Scene {
     pub map: GraphicsMap,
     pub lights: Vec<Light>,
+    /// Translation of each map model, for movers. Missing entries are untranslated.
+    pub model_offsets: Vec<na::Vec3<f32>>,
 }
 
 pub fn draw_scene<S: glium::Surface>(surface: &mut S,
                                      scene: &Scene,
                                      view: &View) {
-    draw_map(surface, &scene.map, view);
+    draw_map(surface, &scene.map, &scene.model_offsets, view);
 }
 
-fn draw_map<S: glium::Surface>(surface: &mut S, map: &GraphicsMap, view: &View) {
+fn draw_map<S: glium::Surface>(surface: &mut S, map: &GraphicsMap, offsets: &[na::Vec3<f32>], view: &View) {
     let drawparams_main = glium::DrawParameters {
         depth_test: glium::DepthTest::IfLess,
         depth_write: true,
@@ -60,32 +63,36 @@ fn draw_map<S: glium::Surface>(surface: &mut S, map: &GraphicsMap, view: &View)
         ..Default::default()
     };
 
-    for face in &map.faces {
-        let color = &map.textures[face.texture as usize];
-        let colorsamp = glium::uniforms::Sampler::new(color)
-            .anisotropy(16)
-            .magnify_filter(glium::uniforms::MagnifySamplerFilter::Nearest)
-            .minify_filter(glium::uniforms::MinifySamplerFilter::LinearMipmapLinear);
+    for (modelidx, &(face_start, n_faces)) in map.models.iter().enumerate() {
+        let offset = offsets.get(modelidx).map(|&o| o).unwrap_or(na::zero());
+        let model = na::Iso3::new(offset, na::zero()).to_homogeneous();
+        for face in &map.faces[face_start as usize .. (face_start + n_faces) as usize] {
+            let color = &map.textures[face.texture as usize];
+            let colorsamp = glium::uniforms::Sampler::new(color)
+                .anisotropy(16)
+                .magnify_filter(glium::uniforms::MagnifySamplerFilter::Nearest)
+                .minify_filter(glium::uniforms::MinifySamplerFilter::LinearMipmapLinear);
 
-        if face.lightmap >= 0 {
-            let lightmap = &map.lightmaps[face.lightmap as usize];
-            let lmsamp = glium::uniforms::Sampler::new(lightmap)
-                .magnify_filter(glium::uniforms::MagnifySamplerFilter::Linear);
+            if face.lightmap >= 0 {
+                let lightmap = &map.lightmaps[face.lightmap as usize];
+                let lmsamp = glium::uniforms::Sampler::new(lightmap)
+                    .magnify_filter(glium::uniforms::MagnifySamplerFilter::Linear);
 
-            let uniforms = uniform! { 
-                w2s: *(view.w2s).as_array(),
-                cam: *(view.cam).as_array(),
-                model: *na::new_identity::<na::Mat4<_>>(4).as_array(), 
-                diffuse: colorsamp,
-                lightmap: lmsamp 
-            };
-            surface.draw(&map.vertices,
-                       &map.indices.slice(face.index_start as usize, face.index_count as usize).unwrap(),
-                       &map.shaders[0],
-                       &uniforms,
-                       &drawparams_main).unwrap();
-        } else {
-    println!("Skipping un-lightmapped face...");
+                let uniforms = uniform! { 
+                    w2s: *(view.w2s).as_array(),
+                    cam: *(view.cam).as_array(),
+                    model: *model.as_array(), 
+                    diffuse: colorsamp,
+                    lightmap: lmsamp 
+                };
+                surface.draw(&map.vertices,
+                           &map.indices.slice(face.index_start as usize, face.index_count as usize).unwrap(),
+                           &map.shaders[0],
+                           &uniforms,
+                           &drawparams_main).unwrap();
+            } else {
+                println!("Skipping un-lightmapped face...");
+            }
         }
     }
 }
@@ -73,10 +73,12 @@ impl PassSystem {
         }
     }
 
+    /// Draws the finished frame into `viewport` of `output`, or all of it if that's `None`.
     pub fn postprocess<S>(&self,
                        input: &PassData, 
                        output: &mut S, 
-                       technique: &Technique) where S: glium::Surface { 
+                       technique: &Technique,
+                       viewport: Option<glium::Rect>) where S: glium::Surface { 
         let diffusesamp = glium::uniforms::Sampler::new(&input.diffuse)
             .magnify_filter(glium::uniforms::MagnifySamplerFilter::Linear);
         let normsamp = glium::uniforms::Sampler::new(&input.normal)
@@ -95,11 +97,16 @@ impl PassSystem {
             light_texture: lightsamp
         };
 
+        let drawparams = glium::DrawParameters {
+            viewport: viewport,
+            ..technique.drawparams.clone()
+        };
+
         output.draw(&self.quad_verts,
                     &glium::index::NoIndices(glium::index::PrimitiveType::TriangleStrip),
                          &technique.shader,
                          &uniforms,
-                         &technique.drawparams).unwrap();
+                         &drawparams).unwrap();
     }
 
     pub fn light_passes(&self,
@@ -10,6 +10,18 @@ use cast::{
 
 const EPS: f32 = 1.0/8.0;
 
+pub const CONTENTS_SOLID: i32 = 0x1;
+pub const CONTENTS_PLAYERCLIP: i32 = 0x10000;
+/// Out of bounds and goal entities. They're solid brushes in the map, but only players care about them.
+pub const CONTENTS_TRIGGER: i32 = 0x40000000;
+
+/// What bullets, rockets and the hook hit.
+pub const MASK_SOLID: i32 = CONTENTS_SOLID;
+/// What players bump into. Ladders are usually clip brushes, so those need to block too.
+pub const MASK_PLAYERSOLID: i32 = CONTENTS_SOLID | CONTENTS_PLAYERCLIP | CONTENTS_TRIGGER;
+
+pub const SURF_LADDER: i32 = 0x8;
+
 fn signcpy(n: f32, from: f32) -> f32 {
     if from >= 0.0 {
         n
@@ -65,12 +77,51 @@ pub struct Brush {
     pub sides: Vec<BrushSide>
 }
 impl Brush {
-    pub fn cast_ray(&self, ray: &Ray, (start, end): (f32, f32)) -> Option<CastResult> {
+    /// A solid axis-aligned box, for colliding with things that aren't part of the map.
+    pub fn from_box(center: &na::Pnt3<f32>, halfextents: &na::Vec3<f32>) -> Brush {
+        let side = |norm: na::Vec3<f32>, extent: f32| BrushSide {
+            plane: Plane {
+                norm: norm,
+                dist: na::dot(&norm, center.as_vec()) + extent,
+            },
+            flags: 0,
+            contents: CONTENTS_SOLID,
+        };
+        Brush {
+            sides: vec![
+                side(na::Vec3::new(1.0, 0.0, 0.0), halfextents.x),
+                side(na::Vec3::new(-1.0, 0.0, 0.0), halfextents.x),
+                side(na::Vec3::new(0.0, 1.0, 0.0), halfextents.y),
+                side(na::Vec3::new(0.0, -1.0, 0.0), halfextents.y),
+                side(na::Vec3::new(0.0, 0.0, 1.0), halfextents.z),
+                side(na::Vec3::new(0.0, 0.0, -1.0), halfextents.z),
+            ]
+        }
+    }
+
+    /// Everything the brush's sides are made of.
+    pub fn contents(&self) -> i32 {
+        self.sides.iter().fold(0, |contents, side| contents | side.contents)
+    }
+
+    /// Whether a box overlaps this brush, whatever its contents.
+    pub fn overlaps_box(&self, center: &na::Pnt3<f32>, halfextents: &na::Vec3<f32>) -> bool {
+        self.sides.iter().all(|side| {
+            let pad = na::abs(&(halfextents.x * side.plane.norm.x)) +
+                na::abs(&(halfextents.y * side.plane.norm.y)) + 
+                na::abs(&(halfextents.z * side.plane.norm.z));
+            side.plane.dist_to_point(center) - pad < 0.0
+        })
+    }
+
+    /// Casts against the sides whose contents are in `mask`.
+    pub fn cast_ray(&self, ray: &Ray, (start, end): (f32, f32), mask: i32) -> Option<CastResult> {
         let mut sf = -1.0;
         let mut ef = 1.0;
         let mut norm = na::zero();
+        let mut flags = 0;
         for side in &self.sides {
-            if !(side.contents & 1 == 1) {
+            if side.contents & mask == 0 {
                 continue;
             }
 
@@ -94,6 +145,7 @@ impl Brush {
                 if frac > sf {
                     sf = frac;
                     norm = side.plane.norm;
+                    flags = side.flags;
                 }
             } else {
                 let frac = (d1 + EPS) / (d1 - d2);
@@ -107,6 +159,7 @@ impl Brush {
             return Some(CastResult {
                 toi: sf,
                 norm: norm,
+                flags: flags,
                 entity: None, 
             });
         }
@@ -134,12 +187,39 @@ impl Tree {
         &self.leaves[(-nodeidx - 1) as usize]
     }
 
-    pub fn cast_ray(&self, ray: &Ray) -> Option<CastResult> {
-        self.cast_ray_recursive(ray, 0, (0.0, 1.0), (ray.orig, (ray.orig.to_vec() + ray.dir).to_pnt()))
+    /// Whether a box overlaps any solid brush in the tree.
+    pub fn overlaps_box(&self, center: &na::Pnt3<f32>, halfextents: &na::Vec3<f32>) -> bool {
+        self.overlaps_box_recursive(0, center, halfextents)
+    }
+
+    fn overlaps_box_recursive(&self, nodeidx: NodeIndex, center: &na::Pnt3<f32>, halfextents: &na::Vec3<f32>) -> bool {
+        if nodeidx < 0 {
+            let leaf = self.get_leaf(nodeidx);
+            let leafbrushes = &self.leafbrushes[leaf.leafbrush as usize..(leaf.leafbrush + leaf.n_leafbrushes) as usize];
+            return leafbrushes.iter().any(|&leafbrush| {
+                let brush = &self.brushes[leafbrush as usize];
+                brush.contents() & (CONTENTS_SOLID | CONTENTS_PLAYERCLIP) != 0 && brush.overlaps_box(center, halfextents)
+            });
+        }
+
+        let InnerNode { ref plane, pos, neg } = self.inodes[nodeidx as usize];
+        let d = plane.dist_to_point(center);
+        let pad = na::abs(&(halfextents.x * plane.norm.x)) +
+            na::abs(&(halfextents.y * plane.norm.y)) +
+            na::abs(&(halfextents.z * plane.norm.z));
+
+        // A box straddling the plane has to be checked on both sides.
+        (d > -pad && self.overlaps_box_recursive(pos, center, halfextents)) ||
+            (d < pad && self.overlaps_box_recursive(neg, center, halfextents))
+    }
+
+    pub fn cast_ray(&self, ray: &Ray, mask: i32) -> Option<CastResult> {
+        self.cast_ray_recursive(ray, mask, 0, (0.0, 1.0), (ray.orig, (ray.orig.to_vec() + ray.dir).to_pnt()))
     }
 
     fn cast_ray_recursive(&self,
                           ray: &Ray,
+                          mask: i32,
                           nodeidx: NodeIndex,
                           (start, end): (f32, f32),
                           (startpos, endpos): (na::Pnt3<f32>, na::Pnt3<f32>))
@@ -155,7 +235,7 @@ impl Tree {
             let mut best = None;
             for &leafbrush in &self.leafbrushes[leaf.leafbrush as usize..(leaf.leafbrush + leaf.n_leafbrushes) as usize] {
                 let brush = &self.brushes[leafbrush as usize];
-                let result = brush.cast_ray(ray, (start, end)); 
+                let result = brush.cast_ray(ray, (start, end), mask);
                 best = combine_results(result, best);
             }
             return best ;
@@ -174,9 +254,9 @@ impl Tree {
         // How does the ray interact with this plane?
         if d1 > (pad ) && d2 > (pad ) {
             // Then just check the front subtree.
-            self.cast_ray_recursive(&ray, pos, (start, end), (startpos, endpos))
+            self.cast_ray_recursive(&ray, mask, pos, (start, end), (startpos, endpos))
         } else if d1 < -(pad ) && d2 < -(pad ) {
-            self.cast_ray_recursive(&ray, neg, (start, end), (startpos, endpos))
+            self.cast_ray_recursive(&ray, mask, neg, (start, end), (startpos, endpos))
         } else {
             let td = d1 - d2;
             let coincident;
@@ -213,7 +293,7 @@ impl Tree {
             let nmid = (startpos.to_vec() + ray.dir * ns).to_pnt();
             let fmid = (startpos.to_vec() + ray.dir * fs).to_pnt();
 
-            combine_results(self.cast_ray_recursive(ray, near, nearbounds, (startpos, nmid)), self.cast_ray_recursive(ray, far, farbounds, (fmid, endpos)))
+            combine_results(self.cast_ray_recursive(ray, mask, near, nearbounds, (startpos, nmid)), self.cast_ray_recursive(ray, mask, far, farbounds, (fmid, endpos)))
         }
     }
 }
@@ -8,6 +8,7 @@ extern crate byteorder;
 extern crate image;
 
 pub mod bsp;
+pub mod mover;
 pub mod q3_import;
 
 use cast::{
@@ -16,32 +17,67 @@ use cast::{
 };
 
 pub struct Model {
+    pub mins: na::Vec3<f32>,
+    pub maxs: na::Vec3<f32>,
+    pub face: u32,
+    pub n_faces: u32,
     pub brush: u32,
     pub n_brushes: u32 
 }
 pub struct Entity {
     pub model: u32,
-    pub kind: EntityKind
+    pub kind: EntityKind,
+    pub mover: Option<mover::Mover>,
+}
+impl Entity {
+    /// Where the entity's brushes currently sit, relative to their compiled position.
+    pub fn offset(&self) -> na::Vec3<f32> {
+        match self.mover {
+            Some(ref mover) => mover.offset,
+            None => na::zero()
+        }
+    }
 }
 #[derive(PartialEq)]
 pub enum EntityKind {
     OutOfBounds,
-    Goal
+    Goal,
+    Solid,
+    /// Hurts players inside it, in damage per second.
+    Hazard(f32),
 }
 
 pub struct Map {
     pub bsp: bsp::Tree,
     pub models: Vec<Model>,
-    pub entities: Vec<Entity>
+    pub entities: Vec<Entity>,
+    /// The player hull the map was built for, by name, if it asks for one.
+    pub hull: Option<String>,
 }
 
 impl Map {
-    pub fn cast_ray(&self, ray: &Ray) -> Option<CastResult> {
-        let mut best = self.bsp.cast_ray(ray);
+    /// Casts against the world and entity brushes whose contents are in `mask`.
+    /// Hazards are never hit: they hurt whoever is inside them, so players have to be able to get in.
+    pub fn cast_ray(&self, ray: &Ray, mask: i32) -> Option<CastResult> {
+        let mut best = self.bsp.cast_ray(ray, mask);
         for (entityidx, entity) in self.entities.iter().enumerate() {
+            let skip = match entity.kind {
+                EntityKind::Hazard(_) => true,
+                EntityKind::OutOfBounds | EntityKind::Goal => mask & bsp::CONTENTS_TRIGGER == 0,
+                EntityKind::Solid => false,
+            };
+            if skip {
+                continue;
+            }
             let model = &self.models[entity.model as usize];
+            // Cast against moved brushes by moving the ray the other way.
+            let localray = Ray {
+                orig: (ray.orig.to_vec() - entity.offset()).to_pnt(),
+                dir: ray.dir,
+                halfextents: ray.halfextents,
+            };
             for brush in &self.bsp.brushes[model.brush as usize .. (model.brush + model.n_brushes) as usize] {
-                let mut brushcast = brush.cast_ray(ray, (0.0, 1.0));
+                let mut brushcast = brush.cast_ray(&localray, (0.0, 1.0), mask);
                 if let Some(brushcast) = brushcast.as_mut() {
                     brushcast.entity = Some(entityidx as u32);
                 }
@@ -51,6 +87,30 @@ impl Map {
 
         best
     }
+
+    /// Indices of the entities whose brushes overlap the given box.
+    pub fn touching(&self, center: &na::Pnt3<f32>, halfextents: &na::Vec3<f32>) -> Vec<u32> {
+        (0..self.entities.len() as u32)
+            .filter(|&entityidx| self.entity_overlaps(entityidx, center, halfextents))
+            .collect()
+    }
+
+    /// Whether an entity's brushes, wherever they currently are, overlap the given box.
+    pub fn entity_overlaps(&self, entityidx: u32, center: &na::Pnt3<f32>, halfextents: &na::Vec3<f32>) -> bool {
+        let entity = &self.entities[entityidx as usize];
+        let model = &self.models[entity.model as usize];
+        let localcenter = (center.to_vec() - entity.offset()).to_pnt();
+        let brushes = &self.bsp.brushes[model.brush as usize .. (model.brush + model.n_brushes) as usize];
+        brushes.iter().any(|brush| brush.overlaps_box(&localcenter, halfextents))
+    }
+
+    /// Whether a box would be stuck in the world or in a solid entity.
+    pub fn box_blocked(&self, center: &na::Pnt3<f32>, halfextents: &na::Vec3<f32>) -> bool {
+        self.bsp.overlaps_box(center, halfextents) ||
+            self.entities.iter().enumerate().any(|(entityidx, entity)| {
+                entity.kind == EntityKind::Solid && self.entity_overlaps(entityidx as u32, center, halfextents)
+            })
+    }
 }
 
 #[derive(Debug)]
@@ -74,6 +134,8 @@ pub struct GraphicsMap {
     pub vertices: glium::VertexBuffer<MapVertex>,
     pub indices: glium::IndexBuffer,
     pub faces: Vec<MapFace>, 
+    /// (first face, face count) for each model.
+    pub models: Vec<(u32, u32)>,
     pub textures: Vec<glium::Texture2d>,
     pub lightmaps: Vec<glium::Texture2d>,
     pub shaders: Vec<glium::Program>,
@@ -95,6 +157,8 @@ pub mod cast {
         pub toi: f32,
         /// Normal of the plane it hit. 
         pub norm: na::Vec3<f32>,
+        /// Surface flags of the brush side it hit.
+        pub flags: i32,
 
         /// Entity hit by the cast.
         pub entity: Option<u32>
@@ -0,0 +1,221 @@
+use na;
+use std::f32;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum MoverKind {
+    /// Opens when touched, waits, then closes again.
+    Door,
+    /// Rests lowered, rises when stood on, waits, then lowers again.
+    Plat,
+    /// Runs along its stops forever.
+    Train,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum MoverState {
+    /// Sitting at a stop until the given time.
+    Waiting { stop: usize, until: f32 },
+    /// Travelling between two stops. `progress` runs from 0 to 1.
+    Moving { from: usize, to: usize, progress: f32 },
+}
+
+/// Drives the brushes of an entity's model along a set of stops.
+#[derive(Clone, Debug)]
+pub struct Mover {
+    pub kind: MoverKind,
+    /// Offsets from the model's compiled position, visited in order.
+    pub stops: Vec<na::Vec3<f32>>,
+    /// Units per second.
+    pub speed: f32,
+    /// Seconds spent at each stop. Negative means forever.
+    pub wait: f32,
+    /// Where the model currently sits, relative to its compiled position.
+    pub offset: na::Vec3<f32>,
+    /// How far the model moved during the last update.
+    pub delta: na::Vec3<f32>,
+    pub state: MoverState,
+}
+
+impl Mover {
+    pub fn new(kind: MoverKind, stops: Vec<na::Vec3<f32>>, speed: f32, wait: f32) -> Mover {
+        let offset = stops[0];
+        let state = match kind {
+            MoverKind::Train => MoverState::Waiting { stop: 0, until: 0.0 },
+            _ => MoverState::Waiting { stop: 0, until: f32::INFINITY },
+        };
+        Mover {
+            kind: kind,
+            stops: stops,
+            speed: speed,
+            wait: wait,
+            offset: offset,
+            delta: na::zero(),
+            state: state,
+        }
+    }
+
+    /// Called when a player touches (doors) or stands on (plats) the mover.
+    pub fn trigger(&mut self, time: f32) {
+        if self.kind == MoverKind::Train {
+            return;
+        }
+        match self.state {
+            MoverState::Waiting { stop: 0, .. } => {
+                self.state = MoverState::Moving { from: 0, to: 1, progress: 0.0 };
+            },
+            MoverState::Waiting { stop, .. } => {
+                // Touching an open door keeps it open.
+                let until = self.wait_until(time);
+                self.state = MoverState::Waiting { stop: stop, until: until };
+            },
+            MoverState::Moving { .. } => ()
+        }
+    }
+
+    pub fn update(&mut self, time: f32, dt: f32) {
+        let old = self.offset;
+        match self.state {
+            MoverState::Waiting { stop, until } => {
+                if time >= until && self.stops.len() > 1 {
+                    let next = self.next_stop(stop);
+                    self.state = MoverState::Moving { from: stop, to: next, progress: 0.0 };
+                }
+            },
+            MoverState::Moving { from, to, progress } => {
+                let start = self.stops[from];
+                let end = self.stops[to];
+                let dist = na::norm(&(end - start));
+                let progress = if dist > 0.0 {
+                    progress + (self.speed * dt) / dist
+                } else {
+                    1.0
+                };
+
+                if progress >= 1.0 {
+                    self.offset = end;
+                    let until = if to == 0 && self.kind != MoverKind::Train {
+                        f32::INFINITY
+                    } else {
+                        self.wait_until(time)
+                    };
+                    self.state = MoverState::Waiting { stop: to, until: until };
+                } else {
+                    self.offset = start + (end - start) * progress;
+                    self.state = MoverState::Moving { from: from, to: to, progress: progress };
+                }
+            }
+        }
+        self.delta = self.offset - old;
+    }
+
+    /// Something's in the way. Doors and plats head back where they came from;
+    /// trains just wait for it to move.
+    pub fn reverse(&mut self) {
+        if self.kind == MoverKind::Train {
+            return;
+        }
+        if let MoverState::Moving { from, to, progress } = self.state {
+            self.state = MoverState::Moving { from: to, to: from, progress: 1.0 - progress };
+        }
+    }
+
+    fn next_stop(&self, stop: usize) -> usize {
+        (stop + 1) % self.stops.len()
+    }
+
+    fn wait_until(&self, time: f32) -> f32 {
+        if self.wait < 0.0 {
+            f32::INFINITY
+        } else {
+            time + self.wait
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use na;
+    use super::{Mover, MoverKind};
+
+    #[test]
+    fn door_opens_and_closes() {
+        let mut door = Mover::new(MoverKind::Door,
+                                  vec![na::zero(), na::Vec3::new(0.0, -64.0, 0.0)],
+                                  64.0,
+                                  1.0);
+        door.update(0.0, 0.5);
+        assert_eq!(door.offset, na::zero());
+
+        door.trigger(0.5);
+        door.update(1.0, 0.5);
+        assert!(na::approx_eq(&door.offset, &na::Vec3::new(0.0, -32.0, 0.0)));
+        assert!(na::approx_eq(&door.delta, &na::Vec3::new(0.0, -32.0, 0.0)));
+
+        door.update(1.5, 0.5);
+        assert!(na::approx_eq(&door.offset, &na::Vec3::new(0.0, -64.0, 0.0)));
+
+        // Waits a second at the top, then comes back down.
+        door.update(2.0, 0.5);
+        door.update(2.5, 0.5);
+        door.update(3.0, 0.5);
+        assert!(na::approx_eq(&door.offset, &na::Vec3::new(0.0, -32.0, 0.0)));
+    }
+
+    #[test]
+    fn plat_rises_when_triggered() {
+        let mut plat = Mover::new(MoverKind::Plat,
+                                  vec![na::Vec3::new(0.0, 64.0, 0.0), na::zero()],
+                                  64.0,
+                                  1.0);
+        // Rests lowered until something stands on it.
+        plat.update(5.0, 0.5);
+        assert!(na::approx_eq(&plat.offset, &na::Vec3::new(0.0, 64.0, 0.0)));
+
+        plat.trigger(5.0);
+        plat.update(5.5, 0.5);
+        assert!(na::approx_eq(&plat.offset, &na::Vec3::new(0.0, 32.0, 0.0)));
+        plat.update(6.0, 0.5);
+        assert!(na::approx_eq(&plat.offset, &na::zero()));
+
+        // Goes back down after waiting, and stays there.
+        for i in 0..8 {
+            plat.update(6.5 + i as f32 * 0.5, 0.5);
+        }
+        assert!(na::approx_eq(&plat.offset, &na::Vec3::new(0.0, 64.0, 0.0)));
+    }
+
+    #[test]
+    fn train_loops_through_stops() {
+        let stops = vec![na::zero(), na::Vec3::new(32.0, 0.0, 0.0), na::Vec3::new(32.0, 0.0, 32.0)];
+        let mut train = Mover::new(MoverKind::Train, stops, 32.0, 0.0);
+        let mut visited = vec![];
+        for i in 0..15 {
+            train.update(i as f32 * 0.5, 0.5);
+            visited.push(train.offset);
+        }
+        // Leaves straight away, reaches each stop in a second, and comes back round to the first.
+        assert!(na::approx_eq(&visited[2], &na::Vec3::new(32.0, 0.0, 0.0)));
+        assert!(visited.iter().any(|&v| na::approx_eq(&v, &na::Vec3::new(32.0, 0.0, 32.0))));
+        assert!(visited[3..].iter().any(|&v| na::approx_eq(&v, &na::zero())));
+        // Trains never turn back, even when blocked.
+        assert!(na::approx_eq(&train.offset, &na::Vec3::new(32.0, 0.0, 16.0)));
+        train.reverse();
+        train.update(7.5, 0.5);
+        assert!(na::approx_eq(&train.offset, &na::Vec3::new(32.0, 0.0, 32.0)));
+    }
+
+    #[test]
+    fn blocked_door_reverses() {
+        let mut door = Mover::new(MoverKind::Door,
+                                  vec![na::zero(), na::Vec3::new(0.0, -64.0, 0.0)],
+                                  64.0,
+                                  1.0);
+        door.trigger(0.0);
+        door.update(0.5, 0.5);
+        door.reverse();
+        door.update(1.0, 0.25);
+        assert!(na::approx_eq(&door.offset, &na::Vec3::new(0.0, -16.0, 0.0)));
+        door.update(1.25, 0.25);
+        assert!(na::approx_eq(&door.offset, &na::zero()));
+    }
+}
@@ -2,14 +2,18 @@
 use bsp;
 use byteorder::{self, LittleEndian, ReadBytesExt};
 use std::io::{Cursor, SeekFrom, Seek};
+use std::collections::HashMap;
 use std;
 use std::borrow::ToOwned;
 use glium;
 use image;
 use na;
+use mover::{Mover, MoverKind};
 use { 
     Map,
     Model,
+    Entity,
+    EntityKind,
     GraphicsMap,
     MapVertex,
     MapFace
@@ -47,6 +51,23 @@ pub fn import(data: &[u8]) -> Result<Map, BspError> {
     let leafbrushes = try!(read_leafbrushes(directory.leafbrushes));
     let models = try!(read_models(directory.models));
 
+    let mut entities = vec![
+        Entity { model: 1, kind: EntityKind::OutOfBounds, mover: None },
+        Entity { model: 2, kind: EntityKind::Goal, mover: None },
+    ];
+    let keyvalues = parse_entities(try!(std::str::from_utf8(directory.entities)));
+    let hull = keyvalues.iter()
+        .find(|ent| ent.get("classname").map(|c| &c[..]) == Some("worldspawn"))
+        .and_then(|ent| ent.get("hull").cloned());
+    for ent in &keyvalues {
+        if let Some(entity) = import_mover(ent, &keyvalues, &models) {
+            entities.push(entity);
+        }
+        if let Some(entity) = import_hazard(ent, &models) {
+            entities.push(entity);
+        }
+    }
+
     Ok(Map {
         bsp: bsp::Tree {
             brushes: brushes,
@@ -55,10 +76,161 @@ pub fn import(data: &[u8]) -> Result<Map, BspError> {
             inodes: nodes,
         },
         models: models, 
-        entities: vec![
-            ::Entity { model: 1, kind: ::EntityKind::OutOfBounds },
-            ::Entity { model: 2, kind: ::EntityKind::Goal },
-        ]
+        entities: entities,
+        hull: hull,
+    })
+}
+
+/// Splits the entity lump into one key/value map per entity.
+fn parse_entities(text: &str) -> Vec<HashMap<String, String>> {
+    let mut entities = vec![];
+    let mut current = None;
+    let mut key: Option<String> = None;
+
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' => current = Some(HashMap::new()),
+            '}' => {
+                if let Some(ent) = current.take() {
+                    entities.push(ent);
+                }
+                key = None;
+            },
+            '"' => {
+                let token: String = chars.by_ref().take_while(|&c| c != '"').collect();
+                if let Some(ref mut ent) = current {
+                    match key.take() {
+                        Some(k) => { ent.insert(k, token); },
+                        None => key = Some(token)
+                    }
+                }
+            },
+            _ => ()
+        }
+    }
+    entities
+}
+
+fn parse_vec(s: &str) -> Option<na::Vec3<f32>> {
+    let parts: Vec<f32> = s.split_whitespace().filter_map(|p| p.parse().ok()).collect();
+    if parts.len() == 3 {
+        Some(na::Vec3::new(parts[0], -parts[2], parts[1]))
+    } else {
+        None
+    }
+}
+
+fn parse_float(ent: &HashMap<String, String>, key: &str, default: f32) -> f32 {
+    ent.get(key).and_then(|v| v.parse().ok()).unwrap_or(default)
+}
+
+/// Direction a door or plat moves in, from its "angle" key.
+fn move_dir(ent: &HashMap<String, String>) -> na::Vec3<f32> {
+    let angle = parse_float(ent, "angle", 0.0);
+    if angle == -1.0 {
+        na::Vec3::new(0.0, -1.0, 0.0)
+    } else if angle == -2.0 {
+        na::Vec3::new(0.0, 1.0, 0.0)
+    } else {
+        let rad = angle.to_radians();
+        na::Vec3::new(rad.cos(), 0.0, rad.sin())
+    }
+}
+
+fn parse_model(ent: &HashMap<String, String>, models: &[Model]) -> Option<u32> {
+    match ent.get("model") {
+        Some(m) if m.starts_with("*") => match m[1..].parse::<u32>() {
+            Ok(m) if (m as usize) < models.len() => Some(m),
+            _ => None
+        },
+        _ => None
+    }
+}
+
+fn import_hazard(ent: &HashMap<String, String>, models: &[Model]) -> Option<Entity> {
+    if ent.get("classname").map(|c| &c[..]) != Some("trigger_hurt") {
+        return None;
+    }
+    let model = match parse_model(ent, models) {
+        Some(m) => m,
+        None => return None
+    };
+    // Q3 hurts every 100ms, or every second with the SLOW flag.
+    let dmg = parse_float(ent, "dmg", 5.0);
+    let slow = parse_float(ent, "spawnflags", 0.0) as i32 & 16 != 0;
+    let rate = if slow { 1.0 } else { 10.0 };
+
+    Some(Entity {
+        model: model,
+        kind: EntityKind::Hazard(dmg * rate),
+        mover: None,
+    })
+}
+
+fn import_mover(ent: &HashMap<String, String>,
+                all: &[HashMap<String, String>],
+                models: &[Model]) -> Option<Entity> {
+    let classname = match ent.get("classname") {
+        Some(c) => &c[..],
+        None => return None
+    };
+    let model = match parse_model(ent, models) {
+        Some(m) => m,
+        None => return None
+    };
+    let size = models[model as usize].maxs - models[model as usize].mins;
+    let lip = parse_float(ent, "lip", 8.0);
+
+    let mover = match classname {
+        "func_door" => {
+            let dir = move_dir(ent);
+            let dist = na::abs(&(dir.x * size.x)) + na::abs(&(dir.y * size.y)) + na::abs(&(dir.z * size.z)) - lip;
+            Mover::new(MoverKind::Door,
+                       vec![na::zero(), dir * dist],
+                       parse_float(ent, "speed", 400.0),
+                       parse_float(ent, "wait", 2.0))
+        },
+        "func_plat" => {
+            let height = parse_float(ent, "height", size.y - lip);
+            Mover::new(MoverKind::Plat,
+                       vec![na::Vec3::new(0.0, height, 0.0), na::zero()],
+                       parse_float(ent, "speed", 200.0),
+                       parse_float(ent, "wait", 1.0))
+        },
+        "func_train" => {
+            // Follow the chain of path_corners until it loops or ends.
+            let mut stops = vec![];
+            let mut target = ent.get("target");
+            while let Some(t) = target {
+                let corner = match all.iter().find(|e| e.get("targetname") == Some(t)) {
+                    Some(corner) => corner,
+                    None => break
+                };
+                match corner.get("origin").and_then(|o| parse_vec(o)) {
+                    Some(origin) => stops.push(origin),
+                    None => break
+                }
+                target = corner.get("target");
+                if stops.len() > all.len() || target == ent.get("target") {
+                    break;
+                }
+            }
+            if stops.is_empty() {
+                return None;
+            }
+            Mover::new(MoverKind::Train,
+                       stops,
+                       parse_float(ent, "speed", 100.0),
+                       parse_float(ent, "wait", 0.0))
+        },
+        _ => return None
+    };
+
+    Some(Entity {
+        model: model,
+        kind: EntityKind::Solid,
+        mover: Some(mover),
     })
 }
 
@@ -69,6 +241,7 @@ pub fn import_graphics_model(data: &[u8], display: &glium::Display) -> Result<Gr
     let meshverts = try!(read_meshverts(directory.meshverts));
     let textures = try!(read_textures(directory.textures));
     let lightmaps = try!(read_lightmaps(directory.lightmaps));
+    let models = try!(read_models(directory.models));
 
     let mut indices = vec![];
     let mut fixed_faces = vec![];
@@ -121,6 +294,7 @@ pub fn import_graphics_model(data: &[u8], display: &glium::Display) -> Result<Gr
         textures: loaded_textures,
         lightmaps: loaded_lightmaps,
         faces: fixed_faces,
+        models: models.iter().map(|m| (m.face, m.n_faces)).collect(),
     })
 }
 
@@ -444,11 +618,24 @@ fn read_vertices(data: &[u8]) -> byteorder::Result<Vec<Vertex>> {
 
 fn read_model(data: &[u8]) -> byteorder::Result<Model> {
     let mut cursor = Cursor::new(data);
-    cursor.seek(SeekFrom::Start(32)).unwrap();
 
+    let min_x = try!(cursor.read_f32::<LittleEndian>());
+    let min_y = try!(cursor.read_f32::<LittleEndian>());
+    let min_z = try!(cursor.read_f32::<LittleEndian>());
+    let max_x = try!(cursor.read_f32::<LittleEndian>());
+    let max_y = try!(cursor.read_f32::<LittleEndian>());
+    let max_z = try!(cursor.read_f32::<LittleEndian>());
+
+    let face = try!(cursor.read_u32::<LittleEndian>()); 
+    let n_faces = try!(cursor.read_u32::<LittleEndian>()); 
     let brush = try!(cursor.read_u32::<LittleEndian>()); 
     let n_brushes = try!(cursor.read_u32::<LittleEndian>()); 
     Ok(Model {
+        // Flipping z into -y swaps which end is the minimum.
+        mins: na::Vec3::new(min_x, -max_z, min_y),
+        maxs: na::Vec3::new(max_x, -min_z, max_y),
+        face: face,
+        n_faces: n_faces,
         brush: brush,
         n_brushes: n_brushes
     })
@@ -458,3 +645,88 @@ fn read_models(data: &[u8]) -> byteorder::Result<Vec<Model>> {
         .map(|chunk| read_model(chunk))
         .collect()
 }
+
+#[cfg(test)]
+pub mod test {
+    use na;
+    use Model;
+    use mover::MoverKind;
+    use super::{parse_entities, import_mover};
+
+    const ENTITIES: &'static str = r#"{
+"classname" "worldspawn"
+}
+{
+"classname" "func_train"
+"model" "*1"
+"target" "t1"
+"speed" "50"
+}
+{
+"classname" "path_corner"
+"targetname" "t1"
+"target" "t2"
+"origin" "0 0 0"
+}
+{
+"classname" "path_corner"
+"targetname" "t2"
+"target" "t1"
+"origin" "64 32 16"
+}
+{
+"classname" "func_plat"
+"model" "*2"
+}
+{
+"classname" "func_door"
+"model" "*1"
+}"#;
+
+    fn models() -> Vec<Model> {
+        (0..3).map(|_| Model {
+            mins: na::Vec3::new(-16.0, -8.0, -16.0),
+            maxs: na::Vec3::new(16.0, 8.0, 16.0),
+            face: 0,
+            n_faces: 0,
+            brush: 0,
+            n_brushes: 0,
+        }).collect()
+    }
+
+    #[test]
+    fn parses_key_values() {
+        let ents = parse_entities(ENTITIES);
+        assert_eq!(ents.len(), 6);
+        assert_eq!(ents[1].get("classname").map(|c| &c[..]), Some("func_train"));
+        assert_eq!(ents[3].get("origin").map(|c| &c[..]), Some("64 32 16"));
+    }
+
+    #[test]
+    fn train_follows_its_path() {
+        let ents = parse_entities(ENTITIES);
+        let train = import_mover(&ents[1], &ents, &models()).unwrap().mover.unwrap();
+        assert_eq!(train.kind, MoverKind::Train);
+        assert_eq!(train.speed, 50.0);
+        // Q3's z-up origins become y-down offsets, and looping back round to t1 ends the path.
+        assert_eq!(train.stops, vec![na::zero(), na::Vec3::new(64.0, -16.0, 32.0)]);
+    }
+
+    #[test]
+    fn plat_rests_lowered() {
+        let ents = parse_entities(ENTITIES);
+        let plat = import_mover(&ents[4], &ents, &models()).unwrap().mover.unwrap();
+        // 16 units tall, less the default 8 unit lip.
+        assert_eq!(plat.stops, vec![na::Vec3::new(0.0, 8.0, 0.0), na::zero()]);
+        assert!(import_mover(&ents[0], &ents, &models()).is_none());
+    }
+
+    #[test]
+    fn door_defaults_match_q3() {
+        let ents = parse_entities(ENTITIES);
+        let door = import_mover(&ents[5], &ents, &models()).unwrap().mover.unwrap();
+        assert_eq!(door.kind, MoverKind::Door);
+        assert_eq!(door.speed, 400.0);
+        assert_eq!(door.wait, 2.0);
+    }
+}